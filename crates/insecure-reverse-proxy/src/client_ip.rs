@@ -0,0 +1,44 @@
+use std::net::{IpAddr, SocketAddr};
+
+use http::request::Parts;
+
+/// Determines the address of the peer a request was received from, which is forwarded to the
+/// upstream in `X-Forwarded-For`.
+///
+/// Implemented for closures taking the request's [`Parts`].
+pub trait ClientIpExtractor: Send + Sync + 'static {
+    /// The address of the peer that sent `request`, or `None` if it is unknown.
+    fn peer_ip(&self, request: &Parts) -> Option<IpAddr>;
+}
+
+impl<F> ClientIpExtractor for F
+where
+    F: Fn(&Parts) -> Option<IpAddr> + Send + Sync + 'static,
+{
+    fn peer_ip(&self, request: &Parts) -> Option<IpAddr> {
+        self(request)
+    }
+}
+
+/// Reads the peer address from a [`SocketAddr`] request extension, or with the `axum` feature
+/// from axum's `ConnectInfo<SocketAddr>`, which `into_make_service_with_connect_info` adds.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeerAddr;
+
+impl ClientIpExtractor for PeerAddr {
+    fn peer_ip(&self, request: &Parts) -> Option<IpAddr> {
+        if let Some(addr) = request.extensions.get::<SocketAddr>() {
+            return Some(addr.ip());
+        }
+
+        #[cfg(feature = "axum")]
+        if let Some(axum::extract::ConnectInfo(addr)) = request
+            .extensions
+            .get::<axum::extract::ConnectInfo<SocketAddr>>()
+        {
+            return Some(addr.ip());
+        }
+
+        None
+    }
+}
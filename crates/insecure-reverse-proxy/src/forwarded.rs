@@ -0,0 +1,398 @@
+use std::net::IpAddr;
+
+use http::{header::HOST, HeaderMap, HeaderName, HeaderValue, Request};
+
+use crate::hyper_reverse_proxy::ProxyError;
+
+pub(crate) const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+pub(crate) const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+pub(crate) const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+pub(crate) const X_FORWARDED_PORT: HeaderName = HeaderName::from_static("x-forwarded-port");
+pub(crate) const FORWARDED: HeaderName = HeaderName::from_static("forwarded");
+
+/// The headers describing the original request, which the upstream needs to build absolute
+/// URLs. `X-Forwarded-For` is always sent.
+///
+/// Headers that are already present, because a trusted proxy sent them, are kept, except that
+/// `X-Forwarded-For` and `Forwarded` are appended to.
+#[derive(Debug, Clone)]
+pub struct ForwardedHeaders {
+    /// Send the original `Host` as `X-Forwarded-Host`.
+    pub x_forwarded_host: bool,
+    /// Send the scheme of the original request, see [`Self::proto`], as `X-Forwarded-Proto`.
+    pub x_forwarded_proto: bool,
+    /// Send the port of the original `Host`, or the default port of the scheme, as
+    /// `X-Forwarded-Port`.
+    pub x_forwarded_port: bool,
+    /// Send the standard `Forwarded` header from RFC 7239.
+    pub forwarded: bool,
+    /// Send the original `Host` to the upstream instead of the upstream's own address.
+    pub preserve_host: bool,
+    /// The scheme clients reach the server with, e.g. `https` when it terminates TLS. HTTP/1.1
+    /// requests do not carry their scheme, so `http` is assumed unless this is set. The scheme
+    /// of absolute request URIs, like the ones of HTTP/2 requests, takes precedence.
+    pub proto: Option<String>,
+}
+
+impl Default for ForwardedHeaders {
+    fn default() -> Self {
+        Self {
+            x_forwarded_host: true,
+            x_forwarded_proto: true,
+            x_forwarded_port: true,
+            forwarded: false,
+            preserve_host: false,
+            proto: None,
+        }
+    }
+}
+
+impl ForwardedHeaders {
+    pub fn x_forwarded_host(mut self, value: bool) -> Self {
+        self.x_forwarded_host = value;
+
+        self
+    }
+
+    pub fn x_forwarded_proto(mut self, value: bool) -> Self {
+        self.x_forwarded_proto = value;
+
+        self
+    }
+
+    pub fn x_forwarded_port(mut self, value: bool) -> Self {
+        self.x_forwarded_port = value;
+
+        self
+    }
+
+    pub fn forwarded(mut self, value: bool) -> Self {
+        self.forwarded = value;
+
+        self
+    }
+
+    pub fn preserve_host(mut self, value: bool) -> Self {
+        self.preserve_host = value;
+
+        self
+    }
+
+    pub fn proto(mut self, value: impl Into<String>) -> Self {
+        self.proto = Some(value.into());
+
+        self
+    }
+}
+
+/// What the upstream is told about the original request, read before it is rewritten.
+pub(crate) struct Origin {
+    host: Option<String>,
+    proto: String,
+}
+
+impl Origin {
+    pub(crate) fn of<B>(request: &Request<B>, policy: &ForwardedHeaders) -> Self {
+        let host = request
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .map(str::to_owned)
+            .or_else(|| request.uri().authority().map(ToString::to_string));
+
+        Self {
+            host,
+            proto: request
+                .uri()
+                .scheme_str()
+                .or(policy.proto.as_deref())
+                .unwrap_or("http")
+                .to_owned(),
+        }
+    }
+
+    fn port(&self) -> Option<String> {
+        let host = self.host.as_deref()?;
+
+        // The port follows the last colon, unless it is part of an IPv6 address.
+        match host.rsplit_once(':') {
+            Some((_, port)) if !port.ends_with(']') => Some(port.to_owned()),
+            _ => match self.proto.as_str() {
+                "http" => Some("80".into()),
+                "https" => Some("443".into()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Add the headers selected by `policy` to the proxied request.
+pub(crate) fn apply(
+    policy: &ForwardedHeaders,
+    client_ip: IpAddr,
+    origin: &Origin,
+    headers: &mut HeaderMap,
+) -> Result<(), ProxyError> {
+    append(headers, X_FORWARDED_FOR, &client_ip.to_string())?;
+
+    // The headers of a trusted proxy describe the client's request, this request came from the
+    // proxy.
+    let client_origin = Origin {
+        host: header_str(headers, &X_FORWARDED_HOST).or_else(|| origin.host.clone()),
+        proto: header_str(headers, &X_FORWARDED_PROTO).unwrap_or_else(|| origin.proto.clone()),
+    };
+
+    if policy.x_forwarded_host {
+        if let Some(host) = &client_origin.host {
+            insert_vacant(headers, X_FORWARDED_HOST, host)?;
+        }
+    }
+
+    if policy.x_forwarded_proto {
+        insert_vacant(headers, X_FORWARDED_PROTO, &client_origin.proto)?;
+    }
+
+    if policy.x_forwarded_port {
+        if let Some(port) = client_origin.port() {
+            insert_vacant(headers, X_FORWARDED_PORT, &port)?;
+        }
+    }
+
+    if policy.forwarded {
+        let node = match client_ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("\"[{ip}]\""),
+        };
+
+        let mut element = format!("for={node}");
+
+        if let Some(host) = &origin.host {
+            element.push_str(";host=");
+            element.push_str(&quote(host));
+        }
+
+        element.push_str(";proto=");
+        element.push_str(&quote(&origin.proto));
+
+        append(headers, FORWARDED, &element)?;
+    }
+
+    if policy.preserve_host {
+        if let Some(host) = &origin.host {
+            headers.insert(HOST, host.parse()?);
+        }
+    }
+
+    Ok(())
+}
+
+fn header_str(headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// Append `value` to the comma separated list in `name`.
+fn append(headers: &mut HeaderMap, name: HeaderName, value: &str) -> Result<(), ProxyError> {
+    let mut list = headers
+        .get_all(&name)
+        .iter()
+        .map(HeaderValue::to_str)
+        .collect::<Result<Vec<_>, _>>()?;
+    list.push(value);
+
+    let value = list.join(", ");
+
+    headers.insert(name, HeaderValue::from_str(&value)?);
+
+    Ok(())
+}
+
+fn insert_vacant(headers: &mut HeaderMap, name: HeaderName, value: &str) -> Result<(), ProxyError> {
+    if !headers.contains_key(&name) {
+        headers.insert(name, HeaderValue::from_str(value)?);
+    }
+
+    Ok(())
+}
+
+/// `value` as a token, or as a quoted string if it contains other characters, like the colon
+/// of a port.
+fn quote(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+
+    if is_token {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin(host: Option<&str>, proto: &str) -> Origin {
+        Origin {
+            host: host.map(str::to_owned),
+            proto: proto.to_owned(),
+        }
+    }
+
+    fn forward(policy: &ForwardedHeaders, client_ip: &str, request: Request<()>) -> HeaderMap {
+        let origin = Origin::of(&request, policy);
+        let mut headers = request.headers().clone();
+
+        apply(policy, client_ip.parse().unwrap(), &origin, &mut headers).unwrap();
+
+        headers
+    }
+
+    #[test]
+    fn reads_the_port_of_the_host() {
+        assert_eq!(
+            origin(Some("localhost:3000"), "http").port().as_deref(),
+            Some("3000")
+        );
+        assert_eq!(
+            origin(Some("example.com"), "http").port().as_deref(),
+            Some("80")
+        );
+        assert_eq!(
+            origin(Some("example.com"), "https").port().as_deref(),
+            Some("443")
+        );
+        assert_eq!(
+            origin(Some("[::1]:8443"), "https").port().as_deref(),
+            Some("8443")
+        );
+        assert_eq!(
+            origin(Some("[::1]"), "https").port().as_deref(),
+            Some("443")
+        );
+        assert_eq!(origin(Some("example.com"), "ws").port(), None);
+        assert_eq!(origin(None, "http").port(), None);
+    }
+
+    #[test]
+    fn resolves_the_proto() {
+        let request = Request::get("/").body(()).unwrap();
+
+        assert_eq!(
+            Origin::of(&request, &ForwardedHeaders::default()).proto,
+            "http"
+        );
+        assert_eq!(
+            Origin::of(&request, &ForwardedHeaders::default().proto("https")).proto,
+            "https"
+        );
+
+        let request = Request::get("https://example.com/").body(()).unwrap();
+
+        assert_eq!(
+            Origin::of(&request, &ForwardedHeaders::default().proto("http")).proto,
+            "https"
+        );
+    }
+
+    #[test]
+    fn quotes_non_tokens() {
+        assert_eq!(quote("https"), "https");
+        assert_eq!(quote("example.com"), "example.com");
+        assert_eq!(quote("localhost:3000"), "\"localhost:3000\"");
+        assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(quote(""), "\"\"");
+    }
+
+    #[test]
+    fn adds_x_forwarded_headers() {
+        let request = Request::get("/api/users")
+            .header(HOST, "localhost:3000")
+            .body(())
+            .unwrap();
+        let headers = forward(&ForwardedHeaders::default(), "127.0.0.1", request);
+
+        assert_eq!(headers[X_FORWARDED_FOR], "127.0.0.1");
+        assert_eq!(headers[X_FORWARDED_HOST], "localhost:3000");
+        assert_eq!(headers[X_FORWARDED_PROTO], "http");
+        assert_eq!(headers[X_FORWARDED_PORT], "3000");
+        assert!(!headers.contains_key(FORWARDED));
+        assert_eq!(headers[HOST], "localhost:3000");
+    }
+
+    #[test]
+    fn keeps_the_headers_of_a_trusted_proxy() {
+        // nginx terminating TLS in front of the server.
+        let request = Request::get("/api/users")
+            .header(HOST, "127.0.0.1:3000")
+            .header(X_FORWARDED_FOR, "203.0.113.7")
+            .header(X_FORWARDED_HOST, "app.example.com")
+            .header(X_FORWARDED_PROTO, "https")
+            .header(FORWARDED, "for=203.0.113.7;proto=https")
+            .body(())
+            .unwrap();
+        let headers = forward(
+            &ForwardedHeaders::default().forwarded(true),
+            "127.0.0.1",
+            request,
+        );
+
+        assert_eq!(headers[X_FORWARDED_FOR], "203.0.113.7, 127.0.0.1");
+        assert_eq!(headers[X_FORWARDED_HOST], "app.example.com");
+        assert_eq!(headers[X_FORWARDED_PROTO], "https");
+        assert_eq!(headers[X_FORWARDED_PORT], "443");
+        assert_eq!(
+            headers[FORWARDED],
+            "for=203.0.113.7;proto=https, for=127.0.0.1;host=\"127.0.0.1:3000\";proto=http"
+        );
+    }
+
+    #[test]
+    fn adds_the_forwarded_header() {
+        let request = Request::get("/")
+            .header(HOST, "example.com")
+            .body(())
+            .unwrap();
+        let policy = ForwardedHeaders::default()
+            .x_forwarded_host(false)
+            .x_forwarded_proto(false)
+            .x_forwarded_port(false)
+            .forwarded(true)
+            .proto("https");
+        let headers = forward(&policy, "2001:db8::1", request);
+
+        assert_eq!(headers[X_FORWARDED_FOR], "2001:db8::1");
+        assert!(!headers.contains_key(X_FORWARDED_HOST));
+        assert!(!headers.contains_key(X_FORWARDED_PROTO));
+        assert!(!headers.contains_key(X_FORWARDED_PORT));
+        assert_eq!(
+            headers[FORWARDED],
+            "for=\"[2001:db8::1]\";host=example.com;proto=https"
+        );
+    }
+
+    #[test]
+    fn preserves_the_host() {
+        let request = Request::get("/")
+            .header(HOST, "localhost:3000")
+            .body(())
+            .unwrap();
+        let mut headers = request.headers().clone();
+        headers.insert(HOST, HeaderValue::from_static("localhost:5173"));
+
+        let policy = ForwardedHeaders::default().preserve_host(true);
+        apply(
+            &policy,
+            "127.0.0.1".parse().unwrap(),
+            &Origin::of(&request, &policy),
+            &mut headers,
+        )
+        .unwrap();
+
+        assert_eq!(headers[HOST], "localhost:3000");
+    }
+}
@@ -0,0 +1,450 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use http_body::Body as HttpBody;
+use hyper_rustls::{FixedServerNameResolver, HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+
+use crate::{InsecureReverseProxyService, ProxyError};
+
+pub type HttpsReverseProxyService<Body> =
+    InsecureReverseProxyService<HttpsConnector<HttpConnector>, Body>;
+
+/// How [`InsecureReverseProxyService::new_https`] verifies the upstream, e.g. a dev server with
+/// a self-signed certificate.
+///
+/// Certificates are verified against the Mozilla roots and the added CAs by default.
+#[derive(Debug, Clone, Default)]
+pub struct HttpsOptions {
+    ca_certificates: Vec<CertificateDer<'static>>,
+    accept_invalid_certs_for_localhost: bool,
+    server_name: Option<String>,
+    http2: bool,
+}
+
+impl HttpsOptions {
+    /// Trust the CA certificates in `pem`, e.g. the root CA of mkcert.
+    pub fn ca_pem(mut self, pem: impl AsRef<[u8]>) -> Result<Self, ProxyError> {
+        let certificates = CertificateDer::pem_slice_iter(pem.as_ref())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| ProxyError::TlsError(error.to_string()))?;
+
+        if certificates.is_empty() {
+            return Err(ProxyError::TlsError("no certificates in PEM".to_owned()));
+        }
+
+        self.ca_certificates.extend(certificates);
+
+        Ok(self)
+    }
+
+    /// Accept any certificate from `localhost`, `*.localhost` and loopback addresses, like the
+    /// ones generated by Vite's `basicSsl` plugin. Other hosts are still verified.
+    pub fn accept_invalid_certs_for_localhost(mut self, value: bool) -> Self {
+        self.accept_invalid_certs_for_localhost = value;
+
+        self
+    }
+
+    /// Send `name` as SNI and verify the certificate against it, instead of the host of the
+    /// target URL.
+    pub fn server_name(mut self, name: impl Into<String>) -> Self {
+        self.server_name = Some(name.into());
+
+        self
+    }
+
+    /// Offer HTTP/2 to the upstream via ALPN, which it may accept instead of HTTP/1.1.
+    ///
+    /// Off by default, since connection upgrades like the WebSocket of Vite's HMR are not
+    /// possible over HTTP/2.
+    pub fn http2(mut self, value: bool) -> Self {
+        self.http2 = value;
+
+        self
+    }
+
+    /// The connector [`InsecureReverseProxyService::new_https`] uses, e.g. for other requests to
+    /// the same upstream.
+    pub fn connector(&self) -> Result<HttpsConnector<HttpConnector>, ProxyError> {
+        let provider = Arc::new(ring::default_provider());
+
+        let mut roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+
+        for certificate in &self.ca_certificates {
+            roots
+                .add(certificate.clone())
+                .map_err(|error| ProxyError::TlsError(error.to_string()))?;
+        }
+
+        let verifier =
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .map_err(|error| ProxyError::TlsError(error.to_string()))?;
+
+        let config = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|error| ProxyError::TlsError(error.to_string()))?;
+
+        let config = if self.accept_invalid_certs_for_localhost {
+            config
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(LocalhostVerifier {
+                    inner: verifier,
+                    provider,
+                }))
+                .with_no_client_auth()
+        } else {
+            config.with_webpki_verifier(verifier).with_no_client_auth()
+        };
+
+        let builder = HttpsConnectorBuilder::new()
+            .with_tls_config(config)
+            .https_or_http();
+
+        let builder = match &self.server_name {
+            Some(name) => {
+                let name = ServerName::try_from(name.clone())
+                    .map_err(|error| ProxyError::TlsError(error.to_string()))?;
+
+                builder.with_server_name_resolver(FixedServerNameResolver::new(name))
+            }
+            None => builder,
+        };
+
+        if self.http2 {
+            Ok(builder.enable_all_versions().build())
+        } else {
+            Ok(builder.enable_http1().build())
+        }
+    }
+}
+
+impl<B> InsecureReverseProxyService<HttpsConnector<HttpConnector>, B> {
+    /// Proxy to a target that may use `https`, verified according to `options`.
+    pub fn new_https(
+        target: impl Into<String>,
+        options: HttpsOptions,
+    ) -> Result<InsecureReverseProxyService<HttpsConnector<HttpConnector>, B>, ProxyError>
+    where
+        B: HttpBody + Send,
+        B::Data: Send,
+    {
+        Ok(Self::new(
+            target,
+            Client::builder(TokioExecutor::new())
+                .pool_idle_timeout(Duration::from_secs(30))
+                .build(options.connector()?),
+        ))
+    }
+}
+
+/// Skips verifying the certificate of local hosts, while still checking that the server owns
+/// the certificate it presents.
+#[derive(Debug)]
+struct LocalhostVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for LocalhostVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if is_localhost(server_name) {
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        self.inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn is_localhost(server_name: &ServerName<'_>) -> bool {
+    match server_name {
+        ServerName::DnsName(name) => {
+            let name = name.as_ref().trim_end_matches('.');
+
+            name.eq_ignore_ascii_case("localhost")
+                || name.to_ascii_lowercase().ends_with(".localhost")
+        }
+        ServerName::IpAddress(ip) => IpAddr::from(*ip).is_loopback(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures_util::stream;
+    use http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+    use http_body_util::{BodyExt, Empty, StreamBody};
+    use hyper::{
+        body::{Bytes, Frame, Incoming},
+        service::service_fn,
+    };
+    use hyper_util::{rt::TokioIo, server::conn::auto};
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+    use rustls::{pki_types::PrivateKeyDer, ServerConfig};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    struct Ca {
+        issuer: Issuer<'static, KeyPair>,
+        pem: String,
+    }
+
+    impl Ca {
+        fn new() -> Self {
+            let mut params = CertificateParams::new(Vec::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let key = KeyPair::generate().unwrap();
+            let pem = params.self_signed(&key).unwrap().pem();
+
+            Self {
+                issuer: Issuer::new(params, key),
+                pem,
+            }
+        }
+
+        fn sign(&self, name: &str) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![name.to_owned()])
+                .unwrap()
+                .signed_by(&key, &self.issuer)
+                .unwrap();
+
+            (cert.der().clone(), key.serialize_der().try_into().unwrap())
+        }
+    }
+
+    fn self_signed(name: &str) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+
+        (cert.der().clone(), key.serialize_der().try_into().unwrap())
+    }
+
+    /// Serve HTTPS with `cert` on an ephemeral port, returning the port. HTTP/2 is offered via
+    /// ALPN, responses contain the version of the request and end with a `grpc-status` trailer.
+    async fn upstream((cert, key): (CertificateDer<'static>, PrivateKeyDer<'static>)) -> u16 {
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+
+                tokio::spawn(async move {
+                    let Ok(stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+
+                    let service = service_fn(|request: Request<Incoming>| async move {
+                        let mut trailers = HeaderMap::new();
+                        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+
+                        let frames = [
+                            Frame::data(Bytes::from(format!("{:?}", request.version()))),
+                            Frame::trailers(trailers),
+                        ];
+                        let body = StreamBody::new(stream::iter(frames.map(Ok::<_, Infallible>)));
+
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .header("trailer", "grpc-status")
+                                .body(body)
+                                .unwrap(),
+                        )
+                    });
+
+                    let _ = auto::Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        port
+    }
+
+    async fn status(target: String, options: HttpsOptions) -> StatusCode {
+        let proxy = HttpsReverseProxyService::<Empty<Bytes>>::new_https(target, options).unwrap();
+        let request = Request::get("/").body(Empty::new()).unwrap();
+
+        let Ok(res) = proxy.oneshot(request).await;
+
+        res.status()
+    }
+
+    #[tokio::test]
+    async fn trusts_ca_pem() {
+        let ca = Ca::new();
+        let port = upstream(ca.sign("localhost")).await;
+        let target = format!("https://localhost:{port}");
+
+        let options = HttpsOptions::default().ca_pem(&ca.pem).unwrap();
+        assert_eq!(status(target.clone(), options).await, StatusCode::OK);
+
+        let options = HttpsOptions::default();
+        assert_eq!(status(target, options).await, StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn rejects_invalid_pem() {
+        assert!(HttpsOptions::default().ca_pem("not a certificate").is_err());
+    }
+
+    #[tokio::test]
+    async fn accepts_invalid_certs_for_localhost_only() {
+        let port = upstream(self_signed("localhost")).await;
+        let options = HttpsOptions::default().accept_invalid_certs_for_localhost(true);
+
+        let target = format!("https://localhost:{port}");
+        assert_eq!(status(target, options.clone()).await, StatusCode::OK);
+
+        let target = format!("https://127.0.0.1:{port}");
+        assert_eq!(status(target, options.clone()).await, StatusCode::OK);
+
+        // Still connects to the local port, but verifies the certificate for another name.
+        let target = format!("https://localhost:{port}");
+        let options = options.server_name("dev.example.com");
+        assert_eq!(status(target, options).await, StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn verifies_server_name() {
+        let ca = Ca::new();
+        let port = upstream(ca.sign("dev.example.com")).await;
+        let target = format!("https://127.0.0.1:{port}");
+        let options = HttpsOptions::default().ca_pem(&ca.pem).unwrap();
+
+        assert_eq!(
+            status(target.clone(), options.clone()).await,
+            StatusCode::BAD_GATEWAY
+        );
+
+        let options = options.server_name("dev.example.com");
+        assert_eq!(status(target, options).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn forwards_trailers_over_negotiated_http2() {
+        let port = upstream(self_signed("localhost")).await;
+        let target = format!("https://localhost:{port}");
+        let options = HttpsOptions::default().accept_invalid_certs_for_localhost(true);
+
+        for (http2, version) in [(true, "HTTP/2.0"), (false, "HTTP/1.1")] {
+            let proxy = HttpsReverseProxyService::<Empty<Bytes>>::new_https(
+                target.clone(),
+                options.clone().http2(http2),
+            )
+            .unwrap();
+            let request = Request::get("/")
+                .header("te", "trailers")
+                .body(Empty::new())
+                .unwrap();
+
+            let Ok(res) = proxy.oneshot(request).await;
+            let body = res.into_body().collect().await.unwrap();
+
+            assert_eq!(body.trailers().unwrap()["grpc-status"], "0");
+            assert_eq!(body.to_bytes(), version);
+        }
+    }
+
+    #[test]
+    fn localhost_names() {
+        for name in [
+            "localhost",
+            "LOCALHOST",
+            "localhost.",
+            "app.localhost",
+            "127.0.0.1",
+            "::1",
+        ] {
+            assert!(is_localhost(&ServerName::try_from(name).unwrap()), "{name}");
+        }
+
+        for name in [
+            "example.com",
+            "localhost.example.com",
+            "notlocalhost",
+            "10.0.0.1",
+        ] {
+            assert!(
+                !is_localhost(&ServerName::try_from(name).unwrap()),
+                "{name}"
+            );
+        }
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::LazyLock;
 
 use http::header::{InvalidHeaderValue, ToStrError, HOST};
 use http::uri::InvalidUri;
-use http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode};
+use http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Version};
 use http_body::Body as HttpBody;
 use hyper::body::Incoming;
 use hyper::upgrade::OnUpgrade;
@@ -13,21 +13,22 @@ use hyper_util::rt::TokioIo;
 use tokio::io::copy_bidirectional;
 use tracing::*;
 
+use crate::forwarded::{self, ForwardedHeaders, Origin};
+
 static TE_HEADER: LazyLock<HeaderName> = LazyLock::new(|| HeaderName::from_static("te"));
 static CONNECTION_HEADER: LazyLock<HeaderName> =
     LazyLock::new(|| HeaderName::from_static("connection"));
 
 static UPGRADE_HEADER: LazyLock<HeaderName> = LazyLock::new(|| HeaderName::from_static("upgrade"));
-static TRAILER_HEADER: LazyLock<HeaderName> = LazyLock::new(|| HeaderName::from_static("trailer"));
 static TRAILERS_HEADER: LazyLock<HeaderName> =
     LazyLock::new(|| HeaderName::from_static("trailers"));
 
-// A list of the headers, using hypers actual HeaderName comparison
-static HOP_HEADERS: LazyLock<[HeaderName; 9]> = LazyLock::new(|| {
+// A list of the headers, using hypers actual HeaderName comparison. `Trailer` is end-to-end and
+// kept, hyper only sends the trailers of HTTP/1.1 messages that it declares.
+static HOP_HEADERS: LazyLock<[HeaderName; 8]> = LazyLock::new(|| {
     [
         CONNECTION_HEADER.clone(),
         TE_HEADER.clone(),
-        TRAILER_HEADER.clone(),
         HeaderName::from_static("keep-alive"),
         HeaderName::from_static("proxy-connection"),
         HeaderName::from_static("proxy-authenticate"),
@@ -37,9 +38,6 @@ static HOP_HEADERS: LazyLock<[HeaderName; 9]> = LazyLock::new(|| {
     ]
 });
 
-static X_FORWARDED_FOR: LazyLock<HeaderName> =
-    LazyLock::new(|| HeaderName::from_static("x-forwarded-for"));
-
 #[derive(Debug, thiserror::Error)]
 pub enum ProxyError {
     #[error("InvalidUri: {0}")]
@@ -52,6 +50,9 @@ pub enum ProxyError {
     ForwardHeaderError,
     #[error("UpgradeError: {0}")]
     UpgradeError(String),
+    #[cfg(feature = "https")]
+    #[error("TlsError: {0}")]
+    TlsError(String),
 }
 
 impl From<HyperError> for ProxyError {
@@ -86,6 +87,7 @@ impl From<InvalidHeaderValue> for ProxyError {
 
 pub struct HyperReverseProxy<T, ReqBody> {
     client: Client<T, ReqBody>,
+    forwarded: ForwardedHeaders,
 }
 
 impl<C: Clone, B> Clone for HyperReverseProxy<C, B> {
@@ -93,13 +95,23 @@ impl<C: Clone, B> Clone for HyperReverseProxy<C, B> {
     fn clone(&self) -> Self {
         Self {
             client: self.client.clone(),
+            forwarded: self.forwarded.clone(),
         }
     }
 }
 
 impl<T, ReqBody> HyperReverseProxy<T, ReqBody> {
     pub fn new(client: Client<T, ReqBody>) -> Self {
-        Self { client }
+        Self {
+            client,
+            forwarded: ForwardedHeaders::default(),
+        }
+    }
+
+    pub fn forwarded_headers(mut self, policy: ForwardedHeaders) -> Self {
+        self.forwarded = policy;
+
+        self
     }
 
     pub async fn call(
@@ -114,7 +126,14 @@ impl<T, ReqBody> HyperReverseProxy<T, ReqBody> {
         ReqBody::Data: Send,
         ReqBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
     {
-        call::<T, ReqBody>(client_ip, &forward_uri, request, &self.client).await
+        call::<T, ReqBody>(
+            client_ip,
+            &forward_uri,
+            request,
+            &self.client,
+            &self.forwarded,
+        )
+        .await
     }
 }
 
@@ -258,6 +277,7 @@ fn create_proxied_request<B>(
     forward_url: &str,
     mut request: Request<B>,
     upgrade_type: Option<&String>,
+    forwarded: &ForwardedHeaders,
 ) -> Result<Request<B>, ProxyError> {
     info!("Creating proxied request");
 
@@ -274,14 +294,20 @@ fn create_proxied_request<B>(
         .unwrap_or(false);
 
     let uri: hyper::Uri = forward_uri(forward_url, &request).parse()?;
+    let origin = Origin::of(&request, forwarded);
 
     debug!("Setting headers of proxied request");
 
-    // remove the original HOST header. It will be set by the client that sends the request
+    // remove the original HOST header. It will be set by the client that sends the request,
+    // unless the forwarding policy preserves it
     request.headers_mut().remove(HOST);
 
     *request.uri_mut() = uri;
 
+    // The version of the upstream connection is negotiated by the client, a request received over
+    // HTTP/2 would be rejected on an HTTP/1.1 connection.
+    *request.version_mut() = Version::HTTP_11;
+
     remove_hop_headers(request.headers_mut());
     remove_connection_headers(request.headers_mut());
 
@@ -305,24 +331,7 @@ fn create_proxied_request<B>(
     }
 
     // Add forwarding information in the headers
-    match request.headers_mut().entry(&*X_FORWARDED_FOR) {
-        hyper::header::Entry::Vacant(entry) => {
-            debug!("X-Fowraded-for header was vacant");
-            entry.insert(client_ip.to_string().parse()?);
-        }
-
-        hyper::header::Entry::Occupied(entry) => {
-            debug!("X-Fowraded-for header was occupied");
-            let client_ip_str = client_ip.to_string();
-            let mut addr =
-                String::with_capacity(entry.get().as_bytes().len() + 2 + client_ip_str.len());
-
-            addr.push_str(std::str::from_utf8(entry.get().as_bytes()).unwrap());
-            addr.push(',');
-            addr.push(' ');
-            addr.push_str(&client_ip_str);
-        }
-    }
+    forwarded::apply(forwarded, client_ip, &origin, request.headers_mut())?;
 
     debug!("Created proxied request");
 
@@ -334,6 +343,7 @@ pub async fn call<T, ReqBody>(
     forward_uri: &str,
     mut request: Request<ReqBody>,
     client: &Client<T, ReqBody>,
+    forwarded: &ForwardedHeaders,
 ) -> Result<Response<Incoming>, ProxyError>
 where
     T: Connect + Clone + Send + Sync + 'static,
@@ -356,6 +366,7 @@ where
         forward_uri,
         request,
         request_upgrade_type.as_ref(),
+        forwarded,
     )?;
 
     let mut response = client.request(proxied_request).await?;
@@ -1,6 +1,14 @@
+mod client_ip;
+mod forwarded;
+#[cfg(feature = "https")]
+mod https;
 mod hyper_reverse_proxy;
+#[cfg(unix)]
+mod unix;
 
 use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
     task::{Context, Poll},
     time::Duration,
 };
@@ -20,11 +28,20 @@ use hyper_util::{
 };
 use tower::Service;
 
-use hyper_reverse_proxy::ProxyError;
+pub use client_ip::{ClientIpExtractor, PeerAddr};
+pub use forwarded::ForwardedHeaders;
+#[cfg(feature = "https")]
+pub use https::{HttpsOptions, HttpsReverseProxyService};
+pub use hyper_reverse_proxy::ProxyError;
+#[cfg(unix)]
+pub use unix::{UnixConnector, UnixReverseProxyService};
 
 pub struct InsecureReverseProxyService<C, Body> {
     pub target: String,
     pub proxy: HyperReverseProxy<C, Body>,
+    client_ip: Arc<dyn ClientIpExtractor>,
+    /// Peers whose forwarding headers are kept and appended to.
+    trusted_proxies: Arc<[IpAddr]>,
 }
 
 pub type HttpReverseProxyService<Body> = InsecureReverseProxyService<HttpConnector, Body>;
@@ -37,8 +54,33 @@ impl<C, B> InsecureReverseProxyService<C, B> {
         Self {
             target: target.into(),
             proxy: HyperReverseProxy::new(client),
+            client_ip: Arc::new(PeerAddr),
+            trusted_proxies: Arc::new([]),
         }
     }
+
+    /// Determine the client address with `extractor` instead of [`PeerAddr`].
+    pub fn client_ip_extractor(mut self, extractor: impl ClientIpExtractor) -> Self {
+        self.client_ip = Arc::new(extractor);
+
+        self
+    }
+
+    /// Which headers tell the upstream about the original request.
+    pub fn forwarded_headers(mut self, policy: ForwardedHeaders) -> Self {
+        self.proxy = self.proxy.forwarded_headers(policy);
+
+        self
+    }
+
+    /// Keep the forwarding headers of requests from these peers, e.g. a load balancer in front
+    /// of the server. The headers of any other peer are replaced, since clients can set them to
+    /// anything.
+    pub fn trusted_proxies(mut self, proxies: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.trusted_proxies = proxies.into_iter().collect();
+
+        self
+    }
 }
 
 impl<B> InsecureReverseProxyService<HttpConnector, B> {
@@ -47,14 +89,30 @@ impl<B> InsecureReverseProxyService<HttpConnector, B> {
         B: HttpBody + Send,
         B::Data: Send,
     {
-        Self {
-            target: target.into(),
-            proxy: HyperReverseProxy::new(
-                Client::builder(TokioExecutor::new())
-                    .pool_idle_timeout(Duration::from_secs(30))
-                    .build_http(),
-            ),
-        }
+        Self::new(
+            target,
+            Client::builder(TokioExecutor::new())
+                .pool_idle_timeout(Duration::from_secs(30))
+                .build_http(),
+        )
+    }
+}
+
+impl<B> InsecureReverseProxyService<HttpConnector, B> {
+    /// Proxy to a target that speaks HTTP/2 over plaintext, without negotiating it first
+    /// ("prior knowledge"). Connection upgrades like WebSockets are not possible over HTTP/2.
+    pub fn new_h2c(target: impl Into<String>) -> InsecureReverseProxyService<HttpConnector, B>
+    where
+        B: HttpBody + Send,
+        B::Data: Send,
+    {
+        Self::new(
+            target,
+            Client::builder(TokioExecutor::new())
+                .pool_idle_timeout(Duration::from_secs(30))
+                .http2_only(true)
+                .build_http(),
+        )
     }
 }
 
@@ -64,6 +122,8 @@ impl<C: Clone, B> Clone for InsecureReverseProxyService<C, B> {
         Self {
             target: self.target.clone(),
             proxy: self.proxy.clone(),
+            client_ip: self.client_ip.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
         }
     }
 }
@@ -89,10 +149,30 @@ where
         let target = self.target.clone();
         let proxy = self.proxy.clone();
 
+        let (mut parts, body) = request.into_parts();
+
+        // Without a known peer the request is attributed to this host.
+        let client_ip = self
+            .client_ip
+            .peer_ip(&parts)
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        if !self.trusted_proxies.contains(&client_ip) {
+            for name in [
+                forwarded::X_FORWARDED_FOR,
+                forwarded::X_FORWARDED_HOST,
+                forwarded::X_FORWARDED_PROTO,
+                forwarded::X_FORWARDED_PORT,
+                forwarded::FORWARDED,
+            ] {
+                parts.headers.remove(name);
+            }
+        }
+
+        let request = Request::from_parts(parts, body);
+
         Box::pin(async move {
-            let res = proxy
-                .call("127.0.0.1".parse().unwrap(), target.clone(), request)
-                .await;
+            let res = proxy.call(client_ip, target.clone(), request).await;
 
             let res = match res {
                 Ok(res) => res.map(Either::Left),
@@ -116,3 +196,231 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use futures_util::stream;
+    use http::{HeaderMap, HeaderValue, Version};
+    use http_body_util::{BodyExt, Empty, StreamBody};
+    use hyper::{
+        body::{Bytes, Frame},
+        service::service_fn,
+    };
+    use hyper_util::{rt::TokioIo, server::conn::auto};
+    use tokio::net::TcpListener;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Serve `builder` on an ephemeral port, returning the port. Responses contain what
+    /// `respond` returns for the request and end with a `grpc-status` trailer, which is declared
+    /// for HTTP/1.1.
+    async fn upstream(
+        builder: auto::Builder<TokioExecutor>,
+        respond: fn(&Request<Incoming>) -> String,
+    ) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let builder = builder.clone();
+
+                tokio::spawn(async move {
+                    let service = service_fn(move |request: Request<Incoming>| async move {
+                        let mut trailers = HeaderMap::new();
+                        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+
+                        let frames = [
+                            Frame::data(Bytes::from(respond(&request))),
+                            Frame::trailers(trailers),
+                        ];
+
+                        let body = StreamBody::new(stream::iter(frames.map(Ok::<_, Infallible>)));
+
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .header("trailer", "grpc-status")
+                                .body(body)
+                                .unwrap(),
+                        )
+                    });
+
+                    let _ = builder
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        port
+    }
+
+    fn version(request: &Request<Incoming>) -> String {
+        format!("{:?}", request.version())
+    }
+
+    /// The forwarding headers of the request, one per line.
+    fn forwarded(request: &Request<Incoming>) -> String {
+        [
+            forwarded::X_FORWARDED_FOR,
+            forwarded::X_FORWARDED_HOST,
+            forwarded::X_FORWARDED_PROTO,
+            forwarded::FORWARDED,
+        ]
+        .iter()
+        .flat_map(|name| {
+            request
+                .headers()
+                .get_all(name)
+                .iter()
+                .map(move |value| format!("{name}: {}", value.to_str().unwrap()))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+
+    /// Serve `proxy` over HTTP/1.1 on an ephemeral port, returning the port.
+    async fn serve(proxy: HttpReverseProxyService<Incoming>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let proxy = proxy.clone();
+
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| proxy.clone().oneshot(request));
+
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        port
+    }
+
+    async fn send<C>(
+        proxy: InsecureReverseProxyService<C, Empty<Bytes>>,
+        version: Version,
+    ) -> (StatusCode, Bytes, Option<HeaderMap>)
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        let request = Request::get("/")
+            .version(version)
+            .header("te", "trailers")
+            .body(Empty::new())
+            .unwrap();
+
+        let Ok(res) = proxy.oneshot(request).await;
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap();
+        let trailers = body.trailers().cloned();
+
+        (status, body.to_bytes(), trailers)
+    }
+
+    #[tokio::test]
+    async fn forwards_trailers_from_h2c() {
+        let port = upstream(
+            auto::Builder::new(TokioExecutor::new()).http2_only(),
+            version,
+        )
+        .await;
+        let proxy = HttpReverseProxyService::new_h2c(format!("http://127.0.0.1:{port}"));
+
+        let (status, body, trailers) = send(proxy, Version::HTTP_11).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "HTTP/2.0");
+        assert_eq!(trailers.unwrap()["grpc-status"], "0");
+    }
+
+    #[tokio::test]
+    async fn sends_http2_requests_over_http1_with_trailers() {
+        let port = upstream(
+            auto::Builder::new(TokioExecutor::new()).http1_only(),
+            version,
+        )
+        .await;
+        let proxy = HttpReverseProxyService::new_http(format!("http://127.0.0.1:{port}"));
+
+        let (status, body, trailers) = send(proxy, Version::HTTP_2).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "HTTP/1.1");
+        assert_eq!(trailers.unwrap()["grpc-status"], "0");
+    }
+
+    #[tokio::test]
+    async fn forwards_declared_trailers_over_http1() {
+        let port = upstream(
+            auto::Builder::new(TokioExecutor::new()).http1_only(),
+            version,
+        )
+        .await;
+        let port = serve(HttpReverseProxyService::new_http(format!(
+            "http://127.0.0.1:{port}"
+        )))
+        .await;
+        // Hyper only sends trailers over HTTP/1.1 that the response declares, so the `Trailer`
+        // header has to make it through the proxy served above to the one sending the request.
+        let proxy = HttpReverseProxyService::new_http(format!("http://127.0.0.1:{port}"));
+
+        let (status, _, trailers) = send(proxy, Version::HTTP_11).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(trailers.unwrap()["grpc-status"], "0");
+    }
+
+    #[tokio::test]
+    async fn replaces_forwarding_headers_of_untrusted_peers() {
+        let port = upstream(auto::Builder::new(TokioExecutor::new()), forwarded).await;
+        let proxy = HttpReverseProxyService::new_http(format!("http://127.0.0.1:{port}"))
+            .forwarded_headers(ForwardedHeaders::default().forwarded(true))
+            .trusted_proxies(["10.0.0.1".parse().unwrap()]);
+
+        let send_from = |peer: &str| {
+            let request = Request::get("/")
+                .header("host", "app.test")
+                .header("x-forwarded-for", "203.0.113.7")
+                .header("x-forwarded-host", "spoofed.test")
+                .header("x-forwarded-proto", "https")
+                .header("forwarded", "for=203.0.113.7")
+                .extension(SocketAddr::new(peer.parse().unwrap(), 50000))
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+
+            proxy.clone().oneshot(request)
+        };
+
+        let Ok(res) = send_from("198.51.100.2").await;
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        assert_eq!(
+            body,
+            "x-forwarded-for: 198.51.100.2\n\
+             x-forwarded-host: app.test\n\
+             x-forwarded-proto: http\n\
+             forwarded: for=198.51.100.2;host=app.test;proto=http"
+        );
+
+        let Ok(res) = send_from("10.0.0.1").await;
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        assert_eq!(
+            body,
+            "x-forwarded-for: 203.0.113.7, 10.0.0.1\n\
+             x-forwarded-host: spoofed.test\n\
+             x-forwarded-proto: https\n\
+             forwarded: for=203.0.113.7, for=10.0.0.1;host=app.test;proto=http"
+        );
+    }
+}
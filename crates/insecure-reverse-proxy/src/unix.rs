@@ -0,0 +1,139 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::future::BoxFuture;
+use http::Uri;
+use http_body::Body as HttpBody;
+use hyper_util::{
+    client::legacy::Client,
+    rt::{TokioExecutor, TokioIo},
+};
+use tokio::net::UnixStream;
+use tower::Service;
+
+use crate::InsecureReverseProxyService;
+
+pub type UnixReverseProxyService<Body> = InsecureReverseProxyService<UnixConnector, Body>;
+
+/// Connects to the Unix domain socket at a path, regardless of the URI being requested.
+#[derive(Debug, Clone)]
+pub struct UnixConnector {
+    path: Arc<Path>,
+}
+
+impl UnixConnector {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into().into(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Service<Uri> for UnixConnector {
+    type Response = TokioIo<UnixStream>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.clone();
+
+        Box::pin(async move { Ok(TokioIo::new(UnixStream::connect(path).await?)) })
+    }
+}
+
+impl<B> InsecureReverseProxyService<UnixConnector, B> {
+    /// Proxy to a server listening on the Unix domain socket at `path`. Requests are sent with
+    /// `Host: localhost`.
+    pub fn new_unix(path: impl Into<PathBuf>) -> InsecureReverseProxyService<UnixConnector, B>
+    where
+        B: HttpBody + Send,
+        B::Data: Send,
+    {
+        Self::new(
+            "http://localhost",
+            Client::builder(TokioExecutor::new())
+                .pool_idle_timeout(Duration::from_secs(30))
+                .build(UnixConnector::new(path)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{header::HOST, Request, Response, StatusCode};
+    use http_body_util::{BodyExt, Empty};
+    use hyper::{body::Bytes, service::service_fn};
+    use hyper_util::server::conn::auto;
+    use tokio::net::UnixListener;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Serve on a Unix domain socket at `path`, answering with the `Host` and path of requests.
+    fn upstream(path: &Path) {
+        let listener = UnixListener::bind(path).unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+
+                tokio::spawn(async move {
+                    let service = service_fn(|request: Request<_>| async move {
+                        let host = request.headers()[HOST].to_str().unwrap().to_owned();
+
+                        Ok::<_, hyper::Error>(Response::new(format!("{host} {}", request.uri())))
+                    });
+
+                    let _ = auto::Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn proxies_to_a_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vite.sock");
+        upstream(&path);
+
+        let proxy = UnixReverseProxyService::<Empty<Bytes>>::new_unix(&path);
+        let request = Request::get("/src/main.ts?t=1700000000000")
+            .header(HOST, "app.example.com")
+            .body(Empty::new())
+            .unwrap();
+
+        let Ok(res) = proxy.oneshot(request).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "localhost /src/main.ts?t=1700000000000");
+    }
+
+    #[tokio::test]
+    async fn fails_without_a_listener() {
+        let dir = tempfile::tempdir().unwrap();
+        let proxy = UnixReverseProxyService::<Empty<Bytes>>::new_unix(dir.path().join("vite.sock"));
+
+        let Ok(res) = proxy
+            .oneshot(Request::get("/").body(Empty::new()).unwrap())
+            .await;
+
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -4,7 +4,7 @@ use axum::Router;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use tower_webdev::{Config, Mode, WebdevService};
+use tower_webdev::{Config, Mode, ShutdownHandle, WebdevService};
 
 #[tokio::main]
 async fn main() {
@@ -17,23 +17,29 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    tokio::join!(serve(using_serve_dir_with_assets_fallback().await, 4000),);
-}
-
-async fn using_serve_dir_with_assets_fallback() -> Router {
     let serve_webdev = WebdevService::new(Config::new_pnpm(Mode::assumed(), "examples/basic-pnpm"))
         .await
         .unwrap();
+    let shutdown = serve_webdev.shutdown_handle();
 
-    Router::new().fallback_service(serve_webdev)
+    serve(Router::new().fallback_service(serve_webdev), 4000, shutdown).await;
 }
 
-async fn serve(app: Router, port: u16) {
+async fn serve(app: Router, port: u16, shutdown: ShutdownHandle) {
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     tracing::debug!("listening on {}", listener.local_addr().unwrap());
 
-    axum::serve(listener, app.layer(TraceLayer::new_for_http()))
+    // `PeerAddr` reads the client address forwarded to the dev server from `ConnectInfo`.
+    let app = app
+        .layer(TraceLayer::new_for_http())
+        .into_make_service_with_connect_info::<SocketAddr>();
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            tokio::signal::ctrl_c().await.unwrap();
+            shutdown.shutdown().await;
+        })
         .await
         .unwrap();
 }
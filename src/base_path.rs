@@ -0,0 +1,196 @@
+//! Mounting the frontend below a path prefix.
+
+use http::{header, HeaderValue, Request, Response, StatusCode, Uri};
+
+use crate::WebdevBody;
+
+/// `path` with a leading and a trailing slash, e.g. `/app/` for `app`.
+pub(crate) fn normalize(path: &str) -> String {
+    let path = path.trim_matches('/');
+
+    if path.is_empty() {
+        "/".into()
+    } else {
+        format!("/{path}/")
+    }
+}
+
+/// The response for requests outside of `base`: `404 Not Found`, or a redirect for the base
+/// itself without its trailing slash.
+pub(crate) fn reject(base: &str, uri: &Uri) -> Option<Response<WebdevBody>> {
+    if base == "/" {
+        return None;
+    }
+
+    if uri.path() == base.trim_end_matches('/') {
+        let location = match uri.query() {
+            Some(query) => format!("{base}?{query}"),
+            None => base.to_owned(),
+        };
+
+        return Some(
+            Response::builder()
+                .status(StatusCode::TEMPORARY_REDIRECT)
+                .header(header::LOCATION, location)
+                .body(WebdevBody::empty())
+                .unwrap(),
+        );
+    }
+
+    if !uri.path().starts_with(base) {
+        return Some(
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(WebdevBody::empty())
+                .unwrap(),
+        );
+    }
+
+    None
+}
+
+/// Remove `base` from the path of `request`. Returns the response for requests outside of
+/// `base`, see [`reject`].
+pub(crate) fn strip<B>(base: &str, request: &mut Request<B>) -> Option<Response<WebdevBody>> {
+    if base == "/" {
+        return None;
+    }
+
+    if let Some(res) = reject(base, request.uri()) {
+        return Some(res);
+    }
+
+    let uri = request.uri();
+    let rest = uri
+        .path()
+        .strip_prefix(base)
+        .expect("paths outside of the base are rejected");
+
+    let path_and_query = match uri.query() {
+        Some(query) => format!("/{rest}?{query}"),
+        None => format!("/{rest}"),
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(
+        path_and_query
+            .parse()
+            .expect("suffix of a valid path is a valid path"),
+    );
+    *request.uri_mut() = Uri::from_parts(parts).expect("only the path changed");
+
+    None
+}
+
+/// Add `base` to a path-absolute `Location`, which the files and the dev server send relative
+/// to the root they are mounted at.
+pub(crate) fn rewrite_location(base: &str, res: &mut Response<WebdevBody>) {
+    if base == "/" {
+        return;
+    }
+
+    let location = res
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .filter(|location| location.starts_with('/') && !location.starts_with("//"))
+        .and_then(|location| {
+            HeaderValue::from_str(&format!("{}{location}", base.trim_end_matches('/'))).ok()
+        });
+
+    if let Some(location) = location {
+        res.headers_mut().insert(header::LOCATION, location);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip_path(base: &str, uri: &str) -> Result<String, StatusCode> {
+        let mut request = Request::get(uri).body(()).unwrap();
+
+        match strip(base, &mut request) {
+            Some(res) => Err(res.status()),
+            None => Ok(request.uri().to_string()),
+        }
+    }
+
+    fn location(base: &str, location: &str) -> String {
+        let mut res = Response::builder()
+            .status(StatusCode::TEMPORARY_REDIRECT)
+            .header(header::LOCATION, location)
+            .body(WebdevBody::empty())
+            .unwrap();
+
+        rewrite_location(base, &mut res);
+
+        res.headers()[header::LOCATION].to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn normalizes_base_paths() {
+        assert_eq!(normalize(""), "/");
+        assert_eq!(normalize("/"), "/");
+        assert_eq!(normalize("app"), "/app/");
+        assert_eq!(normalize("/app"), "/app/");
+        assert_eq!(normalize("/admin/ui/"), "/admin/ui/");
+    }
+
+    #[test]
+    fn strips_the_base() {
+        assert_eq!(
+            strip_path("/app/", "/app/assets/index-BkA0k5xJ.js").as_deref(),
+            Ok("/assets/index-BkA0k5xJ.js")
+        );
+        assert_eq!(strip_path("/app/", "/app/").as_deref(), Ok("/"));
+        assert_eq!(
+            strip_path("/app/", "/app/search?q=a%20b").as_deref(),
+            Ok("/search?q=a%20b")
+        );
+        assert_eq!(
+            strip_path("/", "/assets/index-BkA0k5xJ.js").as_deref(),
+            Ok("/assets/index-BkA0k5xJ.js")
+        );
+    }
+
+    #[test]
+    fn rejects_paths_outside_of_the_base() {
+        assert_eq!(
+            strip_path("/app/", "/assets/index-BkA0k5xJ.js"),
+            Err(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(
+            strip_path("/app/", "/application/"),
+            Err(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(strip_path("/app/", "/"), Err(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn redirects_the_base_without_its_trailing_slash() {
+        let res = reject("/app/", &"/app?lang=de".parse().unwrap()).unwrap();
+
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(res.headers()[header::LOCATION], "/app/?lang=de");
+
+        assert!(reject("/app/", &"/app/@vite/client".parse().unwrap()).is_none());
+        assert!(reject("/", &"/anything".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn rewrites_path_absolute_locations() {
+        assert_eq!(location("/app/", "/docs/"), "/app/docs/");
+        assert_eq!(location("/app/", "/about?ref=nav"), "/app/about?ref=nav");
+        assert_eq!(
+            location("/app/", "//cdn.example.com/"),
+            "//cdn.example.com/"
+        );
+        assert_eq!(
+            location("/app/", "https://example.com/login"),
+            "https://example.com/login"
+        );
+        assert_eq!(location("/app/", "docs/"), "docs/");
+        assert_eq!(location("/", "/docs/"), "/docs/");
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use http_body_util::Full;
+use insecure_reverse_proxy::InsecureReverseProxyServiceBody;
+use pin_project::pin_project;
+use tower_http::services::fs::ServeFileSystemResponseBody;
+
+use crate::transform::HtmlBody;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The response body of a [`WebdevService`](crate::WebdevService).
+#[pin_project(project = WebdevBodyProj)]
+pub enum WebdevBody {
+    /// A file served from `target`.
+    ServeDir(#[pin] ServeFileSystemResponseBody),
+    /// A response from the dev server.
+    Proxy(#[pin] InsecureReverseProxyServiceBody),
+    /// A body that is held in memory.
+    Full(#[pin] Full<Bytes>),
+    /// An HTML document rewritten by an [`HtmlTransform`](crate::HtmlTransform).
+    Html(#[pin] HtmlBody),
+}
+
+impl WebdevBody {
+    pub fn full(value: impl Into<Bytes>) -> Self {
+        Self::Full(Full::new(value.into()))
+    }
+
+    pub fn empty() -> Self {
+        Self::full(Bytes::new())
+    }
+}
+
+impl HttpBody for WebdevBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.project() {
+            WebdevBodyProj::ServeDir(body) => body.poll_frame(cx).map_err(Into::into),
+            WebdevBodyProj::Proxy(body) => body.poll_frame(cx),
+            WebdevBodyProj::Full(body) => body.poll_frame(cx).map_err(|error| match error {}),
+            WebdevBodyProj::Html(body) => body.poll_frame(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            Self::ServeDir(body) => body.is_end_stream(),
+            Self::Proxy(body) => body.is_end_stream(),
+            Self::Full(body) => body.is_end_stream(),
+            Self::Html(body) => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            Self::ServeDir(body) => body.size_hint(),
+            Self::Proxy(body) => body.size_hint(),
+            Self::Full(body) => body.size_hint(),
+            Self::Html(body) => body.size_hint(),
+        }
+    }
+}
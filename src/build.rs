@@ -0,0 +1,265 @@
+//! Building the frontend from a cargo build script.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+use crate::{
+    compress,
+    fingerprint::Fingerprint,
+    webdev_service::{is_excluded_dir, OutputFormat},
+    Config, WebdevError,
+};
+
+/// The file in `OUT_DIR` that [`embed_assets!`](crate::embed_assets) includes.
+const EMBEDDED_ASSETS: &str = "tower_webdev_assets.rs";
+
+/// Builds the frontend from `build.rs`, telling cargo which files the build depends on so that it
+/// only reruns when one of them changed.
+///
+/// The output of the frontend tooling is reported as `cargo:warning` lines.
+pub struct Builder {
+    config: Config,
+    embed: bool,
+}
+
+impl Builder {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            embed: false,
+        }
+    }
+
+    /// Generate the code for embedding `target` into the binary after building. Include it with
+    /// [`embed_assets!`](crate::embed_assets), which requires the `embed` feature.
+    pub fn embed(mut self) -> Self {
+        self.embed = true;
+
+        self
+    }
+
+    /// Print the `cargo:rerun-if-changed` directives, then install dependencies and build.
+    pub fn run(self) -> Result<(), WebdevError> {
+        let mut config = self.config;
+        config.output_format = OutputFormat::CargoWarning;
+
+        for path in rerun_paths(&config)? {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+
+        config.prebuild()?;
+
+        if self.embed {
+            write_embedded_assets(&config)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like [`Self::run`], but fails the cargo build with the frontend's output on error.
+    pub fn build(self) {
+        if let Err(error) = self.run() {
+            panic!("frontend build failed: {error}");
+        }
+    }
+}
+
+/// Files at the root of the project that configure the build even if they are not build inputs.
+const CONFIG_FILE_SUFFIXES: &[&str] = &[".config.js", ".config.mjs", ".config.cjs", ".config.ts"];
+
+fn rerun_paths(config: &Config) -> Result<Vec<PathBuf>, WebdevError> {
+    let root = config.root_dir()?;
+    let target = config.target_dir()?;
+
+    let inputs = config
+        .build_input_files(&root)?
+        .into_iter()
+        .map(|path| root.join(path))
+        .collect::<HashSet<_>>();
+
+    // Cargo watches directories recursively, which picks up newly added files, but directories
+    // containing dependencies or build output would change on every build.
+    let mut paths = Vec::new();
+
+    if watched_dirs(&root, &target, &inputs, &mut paths)? {
+        paths.push(root.clone());
+    }
+
+    let mut files = inputs.into_iter().collect::<Vec<_>>();
+
+    files.push(root.join("package.json"));
+    files.extend(
+        config
+            .package_manager
+            .lockfiles()
+            .iter()
+            .map(|lockfile| root.join(lockfile)),
+    );
+
+    for entry in std::fs::read_dir(&root)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+
+        let is_config = CONFIG_FILE_SUFFIXES.iter().any(|s| name.ends_with(s))
+            || (name.starts_with("tsconfig") && name.ends_with(".json"))
+            || name.starts_with(".env");
+
+        if is_config {
+            files.push(root.join(&*name));
+        }
+    }
+
+    files.sort();
+    files.dedup();
+
+    // Cargo always reruns the build script if a watched file does not exist.
+    files.retain(|file| file.exists() && !paths.iter().any(|dir| file.starts_with(dir)));
+    paths.extend(files);
+
+    Ok(paths)
+}
+
+/// Collect the directories below `dir` that only contain build inputs. Returns whether `dir`
+/// itself only contains build inputs, in which case its subdirectories are not collected.
+fn watched_dirs(
+    dir: &Path,
+    target: &Path,
+    inputs: &HashSet<PathBuf>,
+    watched: &mut Vec<PathBuf>,
+) -> Result<bool, WebdevError> {
+    let mut only_inputs = true;
+    let mut watched_children = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if !entry.file_type()?.is_dir() {
+            only_inputs &= inputs.contains(&path);
+        } else if is_excluded_dir(&path, target) {
+            only_inputs = false;
+        } else if watched_dirs(&path, target, inputs, watched)? {
+            watched_children.push(path);
+        } else {
+            only_inputs = false;
+        }
+    }
+
+    if !only_inputs {
+        watched_children.sort();
+        watched.extend(watched_children);
+    }
+
+    Ok(only_inputs)
+}
+
+/// Write the code for [`EmbeddedAssets`](crate::EmbeddedAssets) to `OUT_DIR`, along with the
+/// compressed variants of each file.
+fn write_embedded_assets(config: &Config) -> Result<(), WebdevError> {
+    let out_dir = std::env::var_os("OUT_DIR")
+        .map(PathBuf::from)
+        .ok_or_else(|| std::io::Error::other("OUT_DIR is not set, is this a build script?"))?;
+
+    let target = config.target_dir()?;
+    let variants_dir = out_dir.join("tower-webdev-assets");
+    std::fs::create_dir_all(&variants_dir)?;
+
+    let mut files = Vec::new();
+
+    for entry in WalkDir::new(&target) {
+        let entry = entry.map_err(std::io::Error::from)?;
+
+        if !entry.file_type().is_file() || compress::is_variant(entry.path()) {
+            continue;
+        }
+
+        let path = entry
+            .path()
+            .strip_prefix(&target)
+            .expect("walked path is inside of target")
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        files.push((path, entry.into_path()));
+    }
+
+    files.sort();
+
+    let mut code = String::from("{\n    static FILES: &[::tower_webdev::EmbeddedFile] = &[\n");
+
+    for (index, (path, absolute)) in files.iter().enumerate() {
+        let contents = std::fs::read(absolute)?;
+        let content_type = mime_guess::from_path(absolute)
+            .first_raw()
+            .unwrap_or("application/octet-stream");
+
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.update(&contents);
+        let etag = format!("\"{}\"", &fingerprint.finish()[..32]);
+
+        let compressible = compress::is_compressible(content_type, contents.len());
+        let mut variants = String::new();
+
+        for (extension, compress) in compress::VARIANTS {
+            let field = match *extension {
+                "br" => "brotli",
+                "gz" => "gzip",
+                _ => "zstd",
+            };
+
+            // Reuse the variants written by `Config::precompress`.
+            let sibling = compress::variant_path(absolute, extension);
+
+            let variant = if sibling.is_file() {
+                format!("Some(include_bytes!({sibling:?}))")
+            } else if compressible {
+                include_variant(
+                    &variants_dir.join(format!("{index}.{extension}")),
+                    compress::smaller(&contents, compress(&contents)?),
+                )?
+            } else {
+                "None".to_owned()
+            };
+
+            variants.push_str(&format!("            {field}: {variant},\n"));
+        }
+
+        code.push_str(&format!(
+            "        ::tower_webdev::EmbeddedFile {{\n            \
+                path: {path:?},\n            \
+                content_type: {content_type:?},\n            \
+                etag: {etag:?},\n            \
+                contents: include_bytes!({absolute:?}),\n\
+                {variants}        \
+            }},\n"
+        ));
+    }
+
+    code.push_str(
+        "    ];\n\n    \
+        static ASSETS: ::tower_webdev::EmbeddedAssets = \
+        ::tower_webdev::EmbeddedAssets::new(FILES);\n\n    \
+        &ASSETS\n}\n",
+    );
+
+    std::fs::write(out_dir.join(EMBEDDED_ASSETS), code)?;
+
+    Ok(())
+}
+
+/// Write `variant` to `path`, returning the expression that includes it.
+fn include_variant(path: &Path, variant: Option<Vec<u8>>) -> Result<String, WebdevError> {
+    let Some(variant) = variant else {
+        return Ok("None".to_owned());
+    };
+
+    std::fs::write(path, variant)?;
+
+    Ok(format!("Some(include_bytes!({path:?}))"))
+}
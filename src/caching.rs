@@ -0,0 +1,310 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{fingerprint::Fingerprint, WebdevBody, WebdevError};
+
+/// `Cache-Control` for files that never change under the same path.
+const IMMUTABLE: HeaderValue = HeaderValue::from_static("public, max-age=31536000, immutable");
+
+/// `Cache-Control` for HTML, which links to the current assets and has to be revalidated.
+const NO_CACHE: HeaderValue = HeaderValue::from_static("no-cache");
+
+/// Controls the `Cache-Control` header of production assets.
+///
+/// Fingerprinted files are cached forever, HTML is revalidated on every request using its
+/// `ETag`, and other files get no `Cache-Control` header unless an override matches.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CachePolicy {
+    /// Treat files with a content hash in their name, like `index-a1b2c3d4.js` or
+    /// `main.3f2a1b9c.css`, as fingerprinted.
+    pub hashed_file_names: bool,
+    /// Path prefixes, relative to `target`, that only contain fingerprinted files.
+    pub immutable_prefixes: Vec<String>,
+    /// `Cache-Control` values for files matching a glob relative to `target`. These take
+    /// precedence over the defaults, and the first matching glob wins.
+    pub overrides: Vec<CacheOverride>,
+}
+
+/// A `Cache-Control` value for the files matching `glob`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheOverride {
+    pub glob: String,
+    pub cache_control: String,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            hashed_file_names: true,
+            immutable_prefixes: vec!["assets/".into()],
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl CachePolicy {
+    pub fn hashed_file_names(mut self, value: bool) -> Self {
+        self.hashed_file_names = value;
+
+        self
+    }
+
+    /// Replace the path prefixes that only contain fingerprinted files.
+    pub fn immutable_prefixes<I, S>(mut self, value: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.immutable_prefixes = value.into_iter().map(Into::into).collect();
+
+        self
+    }
+
+    /// Send `cache_control` for the files matching `glob`, e.g. `("robots.txt", "max-age=3600")`.
+    pub fn cache_control(
+        mut self,
+        glob: impl Into<String>,
+        cache_control: impl Into<String>,
+    ) -> Self {
+        self.overrides.push(CacheOverride {
+            glob: glob.into(),
+            cache_control: cache_control.into(),
+        });
+
+        self
+    }
+
+    pub(crate) fn compile(&self) -> Result<CacheRules, WebdevError> {
+        let mut builder = GlobSetBuilder::new();
+        let mut values = Vec::with_capacity(self.overrides.len());
+
+        for cache_override in &self.overrides {
+            builder.add(Glob::new(&cache_override.glob)?);
+            values.push(
+                HeaderValue::from_str(&cache_override.cache_control).map_err(|_| {
+                    WebdevError::InvalidCacheControl(cache_override.cache_control.clone())
+                })?,
+            );
+        }
+
+        Ok(CacheRules {
+            policy: self.clone(),
+            overrides: builder.build()?,
+            values,
+        })
+    }
+}
+
+/// A [`CachePolicy`] with its globs compiled.
+pub(crate) struct CacheRules {
+    policy: CachePolicy,
+    overrides: GlobSet,
+    values: Vec<HeaderValue>,
+}
+
+impl CacheRules {
+    /// Set `Cache-Control` on the response for the file at `path`, relative to `target`. HTML
+    /// without an `ETag` gets one derived from its path, modification time and length, which is
+    /// checked against `if_none_match`.
+    pub(crate) fn apply(
+        &self,
+        path: &str,
+        if_none_match: &[HeaderValue],
+        mut res: Response<WebdevBody>,
+    ) -> Response<WebdevBody> {
+        if !matches!(
+            res.status(),
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+        ) {
+            return res;
+        }
+
+        let is_html = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+
+        if let Some(cache_control) = self.cache_control(path.trim_start_matches('/'), is_html) {
+            res.headers_mut()
+                .insert(header::CACHE_CONTROL, cache_control);
+        }
+
+        if !is_html || res.status() != StatusCode::OK {
+            return res;
+        }
+
+        let etag = match res.headers().get(header::ETAG) {
+            Some(etag) => etag.clone(),
+            None => {
+                let Some(etag) = weak_etag(path, res.headers()) else {
+                    return res;
+                };
+
+                res.headers_mut().insert(header::ETAG, etag.clone());
+
+                etag
+            }
+        };
+
+        if etag_matches(if_none_match, etag.to_str().unwrap_or_default()) {
+            *res.status_mut() = StatusCode::NOT_MODIFIED;
+            res.headers_mut().remove(header::CONTENT_LENGTH);
+            *res.body_mut() = WebdevBody::empty();
+        }
+
+        res
+    }
+
+    fn cache_control(&self, path: &str, is_html: bool) -> Option<HeaderValue> {
+        if let Some(index) = self.overrides.matches(path).first() {
+            return Some(self.values[*index].clone());
+        }
+
+        let immutable = self
+            .policy
+            .immutable_prefixes
+            .iter()
+            .any(|prefix| path.starts_with(prefix.trim_start_matches('/')))
+            || (self.policy.hashed_file_names && is_hashed(path));
+
+        if immutable {
+            Some(IMMUTABLE)
+        } else if is_html {
+            Some(NO_CACHE)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether the file name contains a content hash, e.g. `index-BkA0k5xJ.js` from Vite or
+/// `main.3f2a1b9c.js` from webpack.
+fn is_hashed(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+
+    // The last part is the extension.
+    let Some((stem, _)) = name.rsplit_once('.') else {
+        return false;
+    };
+
+    // Vite's base64url hashes contain `-` and `_` themselves, like `B_xk-9aQ` in
+    // `index-B_xk-9aQ.js`, so a hash is whatever follows a `-` or `.` up to the next `.`.
+    stem.char_indices()
+        .filter(|&(index, c)| index > 0 && (c == '-' || c == '.'))
+        .filter_map(|(index, _)| stem[index + 1..].split('.').next())
+        .any(|part| {
+            let is_alphanumeric = part.bytes().all(|b| b.is_ascii_alphanumeric());
+            let is_base64url = part
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+            let has_digit = part.bytes().any(|b| b.is_ascii_digit());
+            let has_mixed_case = part.bytes().any(|b| b.is_ascii_uppercase())
+                && part.bytes().any(|b| b.is_ascii_lowercase());
+
+            // Words like `datepicker` are not hashes, but hashes are very unlikely to be all
+            // lowercase letters. With separators, versions like `es-4-17-21` are not either.
+            (8..=64).contains(&part.len())
+                && ((is_alphanumeric && (has_digit || has_mixed_case))
+                    || (is_base64url && has_mixed_case))
+        })
+}
+
+/// An `ETag` derived from the path and the `Last-Modified` and `Content-Length` headers.
+fn weak_etag(path: &str, headers: &HeaderMap) -> Option<HeaderValue> {
+    let last_modified = headers.get(header::LAST_MODIFIED)?;
+    let content_length = headers.get(header::CONTENT_LENGTH)?;
+
+    let mut fingerprint = Fingerprint::new();
+    fingerprint.update(path.as_bytes());
+    fingerprint.update(last_modified.as_bytes());
+    fingerprint.update(content_length.as_bytes());
+
+    HeaderValue::from_str(&format!("W/\"{}\"", &fingerprint.finish()[..32])).ok()
+}
+
+/// Whether any `If-None-Match` value matches `etag`, using the weak comparison.
+pub(crate) fn etag_matches<'a>(
+    if_none_match: impl IntoIterator<Item = &'a HeaderValue>,
+    etag: &str,
+) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    if_none_match
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|candidate| candidate.trim().trim_start_matches("W/"))
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_hashed_file_names() {
+        // Vite
+        assert!(is_hashed("assets/index-BkA0k5xJ.js"));
+        assert!(is_hashed("assets/index-B_xk-9aQ.js"));
+        assert!(is_hashed("assets/vendor-react-Dq3_-xYz.js"));
+        // Vite 2 and Rollup with hex hashes
+        assert!(is_hashed("assets/index.4f3a9c1b.css"));
+        // Create React App
+        assert!(is_hashed("static/js/main.3f2a1b9c.chunk.js"));
+        assert!(is_hashed("static/js/787.8d1e0c2a.chunk.js"));
+        // Astro
+        assert!(is_hashed("_astro/Layout.Dk2qV7Ws.css"));
+    }
+
+    #[test]
+    fn ignores_unhashed_file_names() {
+        assert!(!is_hashed("index.html"));
+        assert!(!is_hashed("favicon.ico"));
+        assert!(!is_hashed("robots"));
+        assert!(!is_hashed("assets/datepicker.js"));
+        assert!(!is_hashed("js/my-component-library.js"));
+        assert!(!is_hashed("vendor/lodash-es-4-17-21.js"));
+        assert!(!is_hashed("vendor/jquery-3.7.1.min.js"));
+        assert!(!is_hashed(".well-known/security.txt"));
+    }
+
+    #[test]
+    fn matches_etags_weakly() {
+        let etag = "\"5f2b-18c3e1f0a42\"";
+
+        assert!(etag_matches(
+            &[HeaderValue::from_static("\"5f2b-18c3e1f0a42\"")],
+            etag
+        ));
+        assert!(etag_matches(
+            &[HeaderValue::from_static("W/\"5f2b-18c3e1f0a42\"")],
+            etag
+        ));
+        assert!(etag_matches(
+            &[HeaderValue::from_static("W/\"5f2b-18c3e1f0a42\"")],
+            "W/\"5f2b-18c3e1f0a42\""
+        ));
+        assert!(etag_matches(
+            &[HeaderValue::from_static("\"1a\", \"5f2b-18c3e1f0a42\"")],
+            etag
+        ));
+        assert!(etag_matches(
+            &[
+                HeaderValue::from_static("\"1a\""),
+                HeaderValue::from_static("\"5f2b-18c3e1f0a42\"")
+            ],
+            etag
+        ));
+        assert!(etag_matches(&[HeaderValue::from_static("*")], etag));
+
+        assert!(!etag_matches(&[], etag));
+        assert!(!etag_matches(&[HeaderValue::from_static("\"5f2b\"")], etag));
+        assert!(!etag_matches(
+            &[HeaderValue::from_static("5f2b-18c3e1f0a42")],
+            etag
+        ));
+    }
+}
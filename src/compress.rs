@@ -0,0 +1,126 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use walkdir::WalkDir;
+
+use crate::WebdevError;
+
+/// Files smaller than this are not worth compressing.
+const MIN_SIZE: usize = 256;
+
+/// Whether a file of `content_type` benefits from compression. Images, fonts and archives are
+/// already compressed.
+pub(crate) fn is_compressible(content_type: &str, len: usize) -> bool {
+    if len < MIN_SIZE {
+        return false;
+    }
+
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+    essence.starts_with("text/")
+        || matches!(
+            essence,
+            "application/javascript"
+                | "application/json"
+                | "application/manifest+json"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+        )
+}
+
+pub(crate) fn gzip(contents: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(contents)?;
+
+    encoder.finish()
+}
+
+pub(crate) fn brotli(contents: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+
+    {
+        let mut encoder = brotli::CompressorWriter::new(&mut output, 4096, 11, 22);
+        encoder.write_all(contents)?;
+    }
+
+    Ok(output)
+}
+
+pub(crate) fn zstd(contents: &[u8]) -> std::io::Result<Vec<u8>> {
+    zstd::bulk::compress(contents, 19)
+}
+
+type Compress = fn(&[u8]) -> std::io::Result<Vec<u8>>;
+
+/// The extensions of precompressed variants, along with their compression.
+pub(crate) const VARIANTS: &[(&str, Compress)] = &[("br", brotli), ("gz", gzip), ("zst", zstd)];
+
+/// The path of the variant of `path` with `extension`, like `app.js.br`.
+pub(crate) fn variant_path(path: &Path, extension: &str) -> PathBuf {
+    let mut variant_path = path.as_os_str().to_owned();
+    variant_path.push(".");
+    variant_path.push(extension);
+
+    variant_path.into()
+}
+
+/// Whether `path` is a precompressed variant of another file, like `app.js.br`.
+pub(crate) fn is_variant(path: &Path) -> bool {
+    let Some(extension) = path.extension() else {
+        return false;
+    };
+
+    VARIANTS.iter().any(|(variant, _)| extension == *variant) && path.with_extension("").is_file()
+}
+
+/// Write the precompressed variants of the compressible files in `target` next to them, for
+/// [`ServeDir`](tower_http::services::ServeDir) to serve.
+pub(crate) fn precompress(target: &Path) -> Result<(), WebdevError> {
+    let mut count = 0;
+
+    for entry in WalkDir::new(target) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let path = entry.path();
+
+        if !entry.file_type().is_file() || is_variant(path) {
+            continue;
+        }
+
+        let content_type = mime_guess::from_path(path).first_raw().unwrap_or_default();
+        let contents = std::fs::read(path)?;
+
+        if !is_compressible(content_type, contents.len()) {
+            continue;
+        }
+
+        for (extension, compress) in VARIANTS {
+            let variant_path = variant_path(path, extension);
+
+            match smaller(&contents, compress(&contents)?) {
+                Some(variant) => std::fs::write(&variant_path, variant)?,
+                // Do not leave a variant of a previous build behind.
+                None => match std::fs::remove_file(&variant_path) {
+                    Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(error.into())
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        count += 1;
+    }
+
+    tracing::info!("precompressed {} files in {}", count, target.display());
+
+    Ok(())
+}
+
+/// Keep a compressed variant only if it actually saves space.
+pub(crate) fn smaller(original: &[u8], compressed: Vec<u8>) -> Option<Vec<u8>> {
+    (compressed.len() < original.len()).then_some(compressed)
+}
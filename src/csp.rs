@@ -0,0 +1,347 @@
+//! `Content-Security-Policy` with a nonce for every HTML response.
+
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::{header, HeaderName, HeaderValue, Uri};
+use serde::{Deserialize, Serialize};
+
+use crate::{transform::HtmlRewrite, WebdevError};
+
+/// The `Content-Security-Policy` sent with HTML documents.
+///
+/// A nonce is generated for every document, added to its `<script>` and `<style>` tags and
+/// allowed by `script-src` and `style-src`. In [`Mode::Development`](crate::Mode) the policy also
+/// allows the dev server's origin and its HMR websocket.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ContentSecurityPolicy {
+    /// The directives, in the order they are sent.
+    pub directives: Vec<CspDirective>,
+    /// Send `Content-Security-Policy-Report-Only`, which reports violations without blocking.
+    pub report_only: bool,
+}
+
+/// A directive like `img-src 'self' data:`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CspDirective {
+    pub name: String,
+    pub sources: Vec<String>,
+}
+
+impl Default for ContentSecurityPolicy {
+    fn default() -> Self {
+        Self {
+            directives: Vec::new(),
+            report_only: false,
+        }
+        .directive("default-src", ["'self'"])
+        .directive("script-src", ["'self'"])
+        .directive("style-src", ["'self'"])
+        .directive("object-src", ["'none'"])
+        .directive("base-uri", ["'self'"])
+    }
+}
+
+impl ContentSecurityPolicy {
+    /// Set the sources of a directive, replacing them if it is already set. Directives without
+    /// sources, like `upgrade-insecure-requests`, take an empty iterator.
+    pub fn directive<I, S>(mut self, name: impl Into<String>, sources: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let directive = CspDirective {
+            name: name.into(),
+            sources: sources.into_iter().map(Into::into).collect(),
+        };
+
+        match self
+            .directives
+            .iter_mut()
+            .find(|existing| existing.name.eq_ignore_ascii_case(&directive.name))
+        {
+            Some(existing) => *existing = directive,
+            None => self.directives.push(directive),
+        }
+
+        self
+    }
+
+    pub fn report_only(mut self, value: bool) -> Self {
+        self.report_only = value;
+
+        self
+    }
+
+    pub(crate) fn header_name(&self) -> HeaderName {
+        if self.report_only {
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            header::CONTENT_SECURITY_POLICY
+        }
+    }
+
+    /// Check that the policy is a valid header value.
+    pub(crate) fn validate(&self) -> Result<(), WebdevError> {
+        HeaderValue::from_str(&self.render(&CspNonce(String::new()), &[]))
+            .map(|_| ())
+            .map_err(|_| WebdevError::InvalidContentSecurityPolicy(join(&self.directives)))
+    }
+
+    /// The header value for a document with `nonce`. The HTTP `dev_sources` are allowed to load
+    /// scripts and styles, and all of them to be connected to.
+    pub(crate) fn header_value(&self, nonce: &CspNonce, dev_sources: &[String]) -> HeaderValue {
+        HeaderValue::from_str(&self.render(nonce, dev_sources))
+            .expect("policy is validated and sources are header values")
+    }
+
+    fn render(&self, nonce: &CspNonce, dev_sources: &[String]) -> String {
+        let mut directives = self.directives.clone();
+
+        if !dev_sources.is_empty() {
+            let default_sources = directives
+                .iter()
+                .find(|directive| directive.name.eq_ignore_ascii_case("default-src"))
+                .map(|directive| directive.sources.clone());
+
+            for name in ["script-src", "style-src", "connect-src"] {
+                let directive = match directives
+                    .iter_mut()
+                    .position(|directive| directive.name.eq_ignore_ascii_case(name))
+                {
+                    Some(index) => &mut directives[index],
+                    // Without the directive, loading falls back to `default-src`, which is
+                    // unrestricted if it is missing too.
+                    None => match &default_sources {
+                        Some(sources) => {
+                            directives.push(CspDirective {
+                                name: name.into(),
+                                sources: sources.clone(),
+                            });
+
+                            directives.last_mut().unwrap()
+                        }
+                        None => continue,
+                    },
+                };
+
+                directive.sources.extend(
+                    dev_sources
+                        .iter()
+                        .filter(|source| name == "connect-src" || source.starts_with("http"))
+                        .cloned(),
+                );
+            }
+        }
+
+        for directive in &mut directives {
+            if ["script-src", "style-src"]
+                .iter()
+                .any(|name| directive.name.eq_ignore_ascii_case(name))
+            {
+                directive.sources.push(format!("'nonce-{nonce}'"));
+            }
+        }
+
+        join(&directives)
+    }
+}
+
+fn join(directives: &[CspDirective]) -> String {
+    directives
+        .iter()
+        .map(CspDirective::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl fmt::Display for CspDirective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+
+        for source in &self.sources {
+            write!(f, " {source}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The nonce of the HTML document being served.
+///
+/// Added to the request extensions when a [`ContentSecurityPolicy`] is configured, so an
+/// [`HtmlTransform`](crate::HtmlTransform) can add it to the tags it inserts, which are not
+/// rewritten themselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    /// 128 random bits, as recommended by the CSP specification.
+    pub(crate) fn generate() -> Self {
+        let mut bytes = [0; 16];
+        getrandom::fill(&mut bytes).expect("the system random number generator is available");
+
+        Self(STANDARD.encode(bytes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CspNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Add `nonce` to the `<script>` and `<style>` tags of the document.
+///
+/// Vite reads the nonce from `<meta property="csp-nonce">` for the styles and preloads it
+/// inserts at runtime, including those of the HMR client.
+pub(crate) fn add_nonce(nonce: &CspNonce, html: &mut HtmlRewrite) {
+    let meta = format!("<meta property=\"csp-nonce\" nonce=\"{nonce}\">");
+    let nonce = nonce.to_string();
+
+    html.prepend_to_head(meta)
+        .element("script, style", move |element| {
+            element.set_attribute("nonce", &nonce)?;
+
+            Ok(())
+        });
+}
+
+/// The sources the dev server needs: its own origin, which it may load modules from, and the
+/// websockets of the HMR client, which connects either to it or through the proxy to `host`.
+pub(crate) fn dev_sources(dev_server: &str, host: Option<&HeaderValue>) -> Vec<String> {
+    let mut sources = Vec::new();
+
+    let uri = dev_server.parse::<Uri>().ok();
+
+    if let Some(authority) = uri.as_ref().and_then(Uri::authority) {
+        let (http, ws) = match uri.as_ref().and_then(Uri::scheme_str) {
+            Some("https") => ("https", "wss"),
+            _ => ("http", "ws"),
+        };
+
+        sources.push(format!("{http}://{authority}"));
+        sources.push(format!("{ws}://{authority}"));
+    }
+
+    // The host is sent by the client, so only well-formed values end up in the policy.
+    let host = host.and_then(|host| host.to_str().ok()).filter(|host| {
+        !host.is_empty()
+            && host
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-.:[]".contains(&b))
+    });
+
+    if let Some(host) = host {
+        sources.push(format!("ws://{host}"));
+        sources.push(format!("wss://{host}"));
+    }
+
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nonce() -> CspNonce {
+        CspNonce("abc".to_owned())
+    }
+
+    fn dev() -> Vec<String> {
+        dev_sources(
+            "http://localhost:5173/app/",
+            Some(&HeaderValue::from_static("example.test:3000")),
+        )
+    }
+
+    #[test]
+    fn adds_nonce_without_dev_sources() {
+        assert_eq!(
+            ContentSecurityPolicy::default().render(&nonce(), &[]),
+            "default-src 'self'; script-src 'self' 'nonce-abc'; style-src 'self' 'nonce-abc'; \
+             object-src 'none'; base-uri 'self'"
+        );
+    }
+
+    #[test]
+    fn copies_default_src_for_dev_sources() {
+        let policy = ContentSecurityPolicy {
+            directives: Vec::new(),
+            report_only: false,
+        }
+        .directive("default-src", ["'self'"])
+        .directive("img-src", ["data:"]);
+
+        assert_eq!(
+            policy.render(&nonce(), &dev()),
+            "default-src 'self'; img-src data:; \
+             script-src 'self' http://localhost:5173 'nonce-abc'; \
+             style-src 'self' http://localhost:5173 'nonce-abc'; \
+             connect-src 'self' http://localhost:5173 ws://localhost:5173 \
+             ws://example.test:3000 wss://example.test:3000"
+        );
+    }
+
+    #[test]
+    fn keeps_unrestricted_directives_without_default_src() {
+        let policy = ContentSecurityPolicy {
+            directives: Vec::new(),
+            report_only: false,
+        }
+        .directive("img-src", ["data:"]);
+
+        assert_eq!(policy.render(&nonce(), &dev()), "img-src data:");
+    }
+
+    #[test]
+    fn extends_existing_directives_with_http_sources_only() {
+        let policy = ContentSecurityPolicy::default().directive("connect-src", ["'self'"]);
+
+        assert_eq!(
+            policy.render(&nonce(), &dev()),
+            "default-src 'self'; \
+             script-src 'self' http://localhost:5173 'nonce-abc'; \
+             style-src 'self' http://localhost:5173 'nonce-abc'; \
+             object-src 'none'; base-uri 'self'; \
+             connect-src 'self' http://localhost:5173 ws://localhost:5173 \
+             ws://example.test:3000 wss://example.test:3000"
+        );
+    }
+
+    #[test]
+    fn dev_sources_of_the_dev_server() {
+        assert_eq!(
+            dev_sources("http://127.0.0.1:5173", None),
+            ["http://127.0.0.1:5173", "ws://127.0.0.1:5173"]
+        );
+        assert_eq!(
+            dev_sources("https://localhost:5173", None),
+            ["https://localhost:5173", "wss://localhost:5173"]
+        );
+        assert!(dev_sources("not a url", None).is_empty());
+    }
+
+    #[test]
+    fn filters_malformed_hosts() {
+        assert_eq!(
+            dev_sources("", Some(&HeaderValue::from_static("[::1]:3000"))),
+            ["ws://[::1]:3000", "wss://[::1]:3000"]
+        );
+
+        for host in [
+            HeaderValue::from_static(""),
+            HeaderValue::from_static("example.test; script-src *"),
+            HeaderValue::from_static("example.test/path"),
+            HeaderValue::from_static("'unsafe-inline'"),
+            HeaderValue::from_bytes(b"ex\xe4mple.test").unwrap(),
+        ] {
+            assert!(dev_sources("", Some(&host)).is_empty(), "{host:?}");
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::sync::LazyLock;
+
+use http::Uri;
+use regex::Regex;
+
+use crate::WebdevError;
+
+/// Matches terminal escape sequences, which dev servers use to highlight their URL.
+static ANSI_ESCAPE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]").unwrap());
+
+/// Patterns for the "server is listening" lines of common dev servers. The first capture group
+/// is the URL.
+static BUILTIN_PATTERNS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| {
+    [
+        // Vite and SvelteKit: `➜  Local:   http://localhost:5173/`
+        // Next.js 13+: `- Local:        http://localhost:3000`
+        ("vite", r"\bLocal:\s+(https?://\S+)"),
+        // Astro: `┃ Local    http://localhost:4321/`
+        ("astro", r"┃\s*Local\s+(https?://\S+)"),
+        // Next.js 12: `ready - started server on 0.0.0.0:3000, url: http://localhost:3000`
+        ("next", r"started server on \S+, url: (https?://\S+)"),
+        // webpack-dev-server 4+: `<i> [webpack-dev-server] Loopback: http://localhost:8080/`
+        ("webpack-dev-server", r"\bLoopback:\s+(https?://\S+)"),
+        // webpack-dev-server 3: `ℹ ｢wds｣: Project is running at http://localhost:8080/`
+        (
+            "webpack-dev-server",
+            r"Project is running at:?\s+(https?://\S+)",
+        ),
+    ]
+    .into_iter()
+    .map(|(name, pattern)| (name, Regex::new(pattern).unwrap()))
+    .collect()
+});
+
+/// Finds the URL a dev server is listening on in its output.
+#[derive(Debug, Clone)]
+pub(crate) struct UrlMatcher {
+    patterns: Vec<Regex>,
+    /// The scheme the dev server is proxied with, `http` or `https`.
+    scheme: &'static str,
+}
+
+impl UrlMatcher {
+    /// Compile the user supplied `patterns`. A pattern's first capture group, or the group named
+    /// `url`, must match either a URL or a bare port, which is served over `scheme`.
+    pub(crate) fn new(patterns: &[String], scheme: &'static str) -> Result<Self, WebdevError> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { patterns, scheme })
+    }
+
+    pub(crate) fn find(&self, line: &str) -> Option<String> {
+        let line = ANSI_ESCAPE.replace_all(line, "");
+
+        let user = self.patterns.iter().map(|pattern| ("user", pattern));
+        let builtin = BUILTIN_PATTERNS
+            .iter()
+            .map(|(name, pattern)| (*name, pattern));
+
+        user.chain(builtin).find_map(|(name, pattern)| {
+            let captures = pattern.captures(&line)?;
+            let found = captures.name("url").or_else(|| captures.get(1))?.as_str();
+
+            tracing::debug!("{} pattern matched dev server output: {}", name, line);
+
+            if found.bytes().all(|b| b.is_ascii_digit()) {
+                return Some(format!("{}://localhost:{found}", self.scheme));
+            }
+
+            // Only the origin is kept, a path is the base the dev server serves at, which the
+            // forwarded requests include already.
+            let url = found.parse::<Uri>().ok()?;
+
+            // The HTTPS client of the proxy connects to plain HTTP URLs too, but not vice versa.
+            if !matches!(url.scheme_str(), Some(scheme) if scheme == "http" || scheme == self.scheme)
+            {
+                tracing::warn!(
+                    "ignoring dev server URL {}, the dev server has to be served over {}",
+                    found,
+                    self.scheme
+                );
+
+                return None;
+            }
+
+            Some(format!("{}://{}", url.scheme_str()?, url.authority()?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(line: &str) -> Option<String> {
+        UrlMatcher::new(&[], "http").unwrap().find(line)
+    }
+
+    #[test]
+    fn finds_builtin_dev_server_urls() {
+        // Vite highlights the port in bold and the arrow in green.
+        assert_eq!(
+            find("  \x1b[32m➜\x1b[39m  \x1b[1mLocal\x1b[22m:   \x1b[36mhttp://localhost:\x1b[1m5173\x1b[22m/\x1b[39m"),
+            Some("http://localhost:5173".into())
+        );
+        assert_eq!(
+            find("   - Local:        http://localhost:3000"),
+            Some("http://localhost:3000".into())
+        );
+        assert_eq!(
+            find("ready - started server on 0.0.0.0:3000, url: http://localhost:3000"),
+            Some("http://localhost:3000".into())
+        );
+        assert_eq!(
+            find("┃ Local    http://localhost:4321/"),
+            Some("http://localhost:4321".into())
+        );
+        assert_eq!(
+            find("<i> [webpack-dev-server] Loopback: http://localhost:8080/, http://[::1]:8080/"),
+            Some("http://localhost:8080".into())
+        );
+        assert_eq!(
+            find("ℹ ｢wds｣: Project is running at http://127.0.0.1:8080/"),
+            Some("http://127.0.0.1:8080".into())
+        );
+    }
+
+    #[test]
+    fn ignores_other_output() {
+        assert_eq!(find("  ➜  Network: use --host to expose"), None);
+        assert_eq!(find("  VITE v5.4.2  ready in 312 ms"), None);
+        assert_eq!(find("[vite] page reload src/App.tsx"), None);
+    }
+
+    #[test]
+    fn keeps_only_the_origin() {
+        // `vite --base /app/`
+        assert_eq!(
+            find("  ➜  Local:   http://localhost:5173/app/"),
+            Some("http://localhost:5173".into())
+        );
+    }
+
+    #[test]
+    fn finds_https_urls_only_when_proxied_over_https() {
+        // `@vitejs/plugin-basic-ssl`
+        let line = "  ➜  Local:   https://localhost:5173/";
+        assert_eq!(find(line), None);
+
+        let matcher = UrlMatcher::new(&[r"listening on port (\d+)".into()], "https").unwrap();
+        assert_eq!(matcher.find(line), Some("https://localhost:5173".into()));
+        assert_eq!(
+            matcher.find("  ➜  Local:   http://localhost:5173/"),
+            Some("http://localhost:5173".into())
+        );
+        assert_eq!(
+            matcher.find("listening on port 4000"),
+            Some("https://localhost:4000".into())
+        );
+        assert_eq!(matcher.find("  ➜  Local:   ftp://localhost:21/"), None);
+    }
+
+    #[test]
+    fn user_patterns_take_precedence() {
+        let matcher = UrlMatcher::new(
+            &[
+                r"listening on port (\d+)".into(),
+                r"(?P<label>serving) at (?P<url>http://\S+)".into(),
+            ],
+            "http",
+        )
+        .unwrap();
+
+        assert_eq!(
+            matcher.find("listening on port 4000"),
+            Some("http://localhost:4000".into())
+        );
+        assert_eq!(
+            matcher.find("serving at http://127.0.0.1:4001/"),
+            Some("http://127.0.0.1:4001".into())
+        );
+        assert_eq!(
+            matcher.find("listening on port 4000, Local: http://localhost:5173/"),
+            Some("http://localhost:4000".into())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_user_patterns() {
+        assert!(UrlMatcher::new(&["(".into()], "http").is_err());
+    }
+}
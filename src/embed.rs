@@ -0,0 +1,254 @@
+//! Serving production assets that are embedded into the binary.
+
+use std::fmt;
+
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use percent_encoding::percent_decode_str;
+
+use crate::{
+    caching::{etag_matches, CacheRules},
+    routing::{self, Route},
+    Routing, WebdevBody,
+};
+
+/// Include the assets embedded by `build::Builder::embed` from a build script. Evaluates to a
+/// `&'static EmbeddedAssets`.
+#[macro_export]
+macro_rules! embed_assets {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/tower_webdev_assets.rs"))
+    };
+}
+
+/// A file of the production build, along with metadata computed at compile time.
+#[derive(Clone, Copy)]
+pub struct EmbeddedFile {
+    /// Path relative to `target`, using `/` as separator.
+    pub path: &'static str,
+    pub content_type: &'static str,
+    /// Strong `ETag` of `contents`. The compressed variants are sent with the encoding appended.
+    pub etag: &'static str,
+    pub contents: &'static [u8],
+    pub gzip: Option<&'static [u8]>,
+    pub brotli: Option<&'static [u8]>,
+    pub zstd: Option<&'static [u8]>,
+}
+
+impl fmt::Debug for EmbeddedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EmbeddedFile")
+            .field("path", &self.path)
+            .field("content_type", &self.content_type)
+            .field("etag", &self.etag)
+            .field("len", &self.contents.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The production build embedded with [`embed_assets!`].
+#[derive(Debug)]
+pub struct EmbeddedAssets {
+    /// Sorted by path.
+    files: &'static [EmbeddedFile],
+}
+
+impl EmbeddedAssets {
+    #[doc(hidden)]
+    pub const fn new(files: &'static [EmbeddedFile]) -> Self {
+        Self { files }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&'static EmbeddedFile> {
+        let files: &'static [EmbeddedFile] = self.files;
+
+        files
+            .binary_search_by(|file| file.path.cmp(path))
+            .ok()
+            .map(|index| &files[index])
+    }
+
+    pub fn files(&self) -> &'static [EmbeddedFile] {
+        self.files
+    }
+}
+
+/// Respond to `request` like [`ServeDir`](tower_http::services::ServeDir) would for the same
+/// files on disk. Range requests are not supported, files are always sent in full.
+pub(crate) fn serve<B>(
+    assets: &EmbeddedAssets,
+    routing: &Routing,
+    cache_rules: &CacheRules,
+    request: &Request<B>,
+) -> Response<WebdevBody> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, "GET,HEAD")
+            .body(WebdevBody::empty())
+            .unwrap();
+    }
+
+    let Ok(path) = percent_decode_str(request.uri().path()).decode_utf8() else {
+        return not_found();
+    };
+
+    let file = match routing.resolve(&path, |path| assets.get(path).is_some()) {
+        Route::File(path) => assets.get(&path),
+        Route::Redirect { trailing_slash } => {
+            return routing::redirect(request.uri(), trailing_slash);
+        }
+        Route::NotFound => None,
+    };
+
+    let Some(file) = file else {
+        return not_found();
+    };
+
+    cache_rules.apply(file.path, &[], serve_file(file, request))
+}
+
+fn serve_file<B>(file: &EmbeddedFile, request: &Request<B>) -> Response<WebdevBody> {
+    let headers = request.headers();
+
+    let (contents, encoding) = match (file.brotli, file.zstd, file.gzip) {
+        (Some(brotli), _, _) if accepts_encoding(headers, "br") => (brotli, Some("br")),
+        (_, Some(zstd), _) if accepts_encoding(headers, "zstd") => (zstd, Some("zstd")),
+        (_, _, Some(gzip)) if accepts_encoding(headers, "gzip") => (gzip, Some("gzip")),
+        _ => (file.contents, None),
+    };
+
+    let etag = encoded_etag(file.etag, encoding);
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, file.content_type)
+        .header(header::ETAG, &etag)
+        .header(header::ACCEPT_RANGES, "none");
+
+    if file.gzip.is_some() || file.brotli.is_some() || file.zstd.is_some() {
+        response = response.header(header::VARY, "accept-encoding");
+    }
+
+    if let Some(encoding) = encoding {
+        response = response.header(header::CONTENT_ENCODING, encoding);
+    }
+
+    if etag_matches(headers.get_all(header::IF_NONE_MATCH), &etag) {
+        return response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(WebdevBody::empty())
+            .unwrap();
+    }
+
+    let body = if request.method() == Method::HEAD {
+        WebdevBody::empty()
+    } else {
+        WebdevBody::full(contents)
+    };
+
+    response
+        .header(header::CONTENT_LENGTH, contents.len())
+        .body(body)
+        .unwrap()
+}
+
+/// The strong `ETag` of the variant in `encoding`. The variants differ byte for byte, so they
+/// cannot share the validator of the uncompressed file.
+fn encoded_etag(etag: &str, encoding: Option<&str>) -> String {
+    match (encoding, etag.strip_suffix('"')) {
+        (Some(encoding), Some(opaque)) => format!("{opaque}-{encoding}\""),
+        (Some(encoding), None) => format!("{etag}-{encoding}"),
+        (None, _) => etag.to_owned(),
+    }
+}
+
+fn not_found() -> Response<WebdevBody> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(WebdevBody::empty())
+        .unwrap()
+}
+
+/// Whether `Accept-Encoding` allows `encoding`, ignoring encodings with `q=0`.
+pub(crate) fn accepts_encoding(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value: &HeaderValue| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|candidate| {
+            let mut parts = candidate.split(';');
+            let name = parts.next().unwrap_or_default().trim();
+            let quality = parts
+                .find_map(|part| part.trim().strip_prefix("q="))
+                .and_then(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            name.eq_ignore_ascii_case(encoding) && quality > 0.0
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: EmbeddedFile = EmbeddedFile {
+        path: "assets/index-BkA0k5xJ.js",
+        content_type: "text/javascript",
+        etag: "\"8d1e0c2a3f2a1b9c\"",
+        contents: b"console.log('hello')",
+        gzip: Some(b"gzip"),
+        brotli: Some(b"br"),
+        zstd: None,
+    };
+
+    fn get(headers: &[(header::HeaderName, &str)]) -> Response<WebdevBody> {
+        let mut request = Request::get("/assets/index-BkA0k5xJ.js");
+
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+
+        serve_file(&FILE, &request.body(()).unwrap())
+    }
+
+    #[test]
+    fn suffixes_the_etag_per_encoding() {
+        let res = get(&[]);
+        assert_eq!(res.headers()[header::ETAG], "\"8d1e0c2a3f2a1b9c\"");
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+
+        let res = get(&[(header::ACCEPT_ENCODING, "gzip, deflate, br, zstd")]);
+        assert_eq!(res.headers()[header::ETAG], "\"8d1e0c2a3f2a1b9c-br\"");
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "br");
+
+        let res = get(&[(header::ACCEPT_ENCODING, "gzip, br;q=0")]);
+        assert_eq!(res.headers()[header::ETAG], "\"8d1e0c2a3f2a1b9c-gzip\"");
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+    }
+
+    #[test]
+    fn revalidates_the_served_encoding() {
+        let res = get(&[
+            (header::ACCEPT_ENCODING, "gzip, br"),
+            (header::IF_NONE_MATCH, "\"8d1e0c2a3f2a1b9c-br\""),
+        ]);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "br");
+
+        // A cached identity response does not validate the brotli variant.
+        let res = get(&[
+            (header::ACCEPT_ENCODING, "gzip, br"),
+            (header::IF_NONE_MATCH, "\"8d1e0c2a3f2a1b9c\""),
+        ]);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "2");
+    }
+
+    #[test]
+    fn ignores_range_requests() {
+        let res = get(&[(header::RANGE, "bytes=0-6")]);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ACCEPT_RANGES], "none");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "20");
+    }
+}
@@ -0,0 +1,66 @@
+use std::{path::PathBuf, process::ExitStatus};
+
+use insecure_reverse_proxy::ProxyError;
+
+/// Errors returned while setting up or running the frontend tooling.
+#[derive(Debug, thiserror::Error)]
+pub enum WebdevError {
+    /// The command could not be started, e.g. because it is not in `$PATH`.
+    #[error("failed to spawn `{command}`: {source}")]
+    Spawn {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The command ran but exited unsuccessfully.
+    #[error("`{command}` exited with {status}\n{stderr}")]
+    CommandFailed {
+        command: String,
+        status: ExitStatus,
+        /// The last lines the command wrote to stdout.
+        stdout: String,
+        /// The last lines the command wrote to stderr.
+        stderr: String,
+    },
+    /// The configured root directory does not exist or is not accessible.
+    #[error("root directory {} is missing: {source}", path.display())]
+    MissingRoot {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The bundler's manifest does not exist in `target`, e.g. because it is not enabled.
+    #[error("manifest {} is missing: {source}", path.display())]
+    MissingManifest {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The bundler's manifest is not in the expected format.
+    #[error("invalid manifest {}: {source}", path.display())]
+    InvalidManifest {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// A pattern for finding the dev server URL is not a valid regular expression.
+    #[error("invalid dev server url pattern: {0}")]
+    InvalidUrlPattern(#[from] regex::Error),
+    /// A glob in the configuration is invalid.
+    #[error("invalid glob: {0}")]
+    InvalidGlob(#[from] globset::Error),
+    /// A `Cache-Control` override is not a valid header value.
+    #[error("invalid Cache-Control value {0:?}")]
+    InvalidCacheControl(String),
+    /// The `Content-Security-Policy` is not a valid header value.
+    #[error("invalid Content-Security-Policy {0:?}")]
+    InvalidContentSecurityPolicy(String),
+    /// The dev server stopped running before it became ready.
+    #[error("dev server stopped running")]
+    DevServerStopped,
+    /// The reverse proxy to the dev server could not be set up.
+    #[error(transparent)]
+    Proxy(#[from] ProxyError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
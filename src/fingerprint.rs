@@ -0,0 +1,75 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+/// A hash over the inputs of a frontend step, used to skip the step when nothing changed.
+pub(crate) struct Fingerprint(Sha256);
+
+impl Fingerprint {
+    pub(crate) fn new() -> Self {
+        Self(Sha256::new())
+    }
+
+    pub(crate) fn update(&mut self, value: impl AsRef<[u8]>) {
+        let value = value.as_ref();
+
+        // Length prefix every value so that adjacent values cannot run into each other.
+        self.0.update((value.len() as u64).to_le_bytes());
+        self.0.update(value);
+    }
+
+    /// Hash the name and contents of `path`. A missing file is hashed as such.
+    pub(crate) fn update_file(&mut self, root: &Path, path: &Path) -> std::io::Result<()> {
+        self.update(path.to_string_lossy().as_bytes());
+
+        match std::fs::read(root.join(path)) {
+            Ok(contents) => {
+                self.update(b"file");
+                self.update(contents);
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                self.update(b"missing");
+            }
+            Err(error) => return Err(error),
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.0
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+/// A file recording the fingerprint of the last successful run of a step.
+pub(crate) struct Stamp {
+    path: PathBuf,
+}
+
+impl Stamp {
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub(crate) fn matches(&self, fingerprint: &str) -> bool {
+        std::fs::read_to_string(&self.path).is_ok_and(|stamp| stamp.trim() == fingerprint)
+    }
+
+    pub(crate) fn save(&self, fingerprint: &str) {
+        let result = match self.path.parent() {
+            Some(parent) => std::fs::create_dir_all(parent),
+            None => Ok(()),
+        };
+
+        if let Err(error) = result.and_then(|()| std::fs::write(&self.path, fingerprint)) {
+            tracing::warn!("failed to write {}: {}", self.path.display(), error);
+        }
+    }
+}
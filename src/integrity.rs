@@ -0,0 +1,264 @@
+//! Subresource Integrity digests of production assets.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::Uri;
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha384};
+use walkdir::WalkDir;
+
+#[cfg(feature = "embed")]
+use crate::EmbeddedAssets;
+use crate::{transform::HtmlRewrite, Config};
+
+/// `sha384` digests of the files in `target`, for the `integrity` attribute of `<script>` and
+/// `<link>` tags.
+///
+/// Digests are computed when a file is first requested and cached from then on, so files must
+/// not change while the server is running.
+#[derive(Clone)]
+pub struct Integrity {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    source: Source,
+    digests: Mutex<HashMap<String, String>>,
+}
+
+enum Source {
+    Dir(PathBuf),
+    #[cfg(feature = "embed")]
+    Embedded(&'static EmbeddedAssets),
+}
+
+impl Source {
+    fn dir(&self) -> Option<&Path> {
+        match self {
+            Source::Dir(target) => Some(target),
+            #[cfg(feature = "embed")]
+            Source::Embedded(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Integrity").finish_non_exhaustive()
+    }
+}
+
+impl Integrity {
+    /// Digests of the production assets of `config`, read from `target` or from the embedded
+    /// assets.
+    pub fn new(config: &Config) -> Self {
+        #[cfg(feature = "embed")]
+        let source = match config.embedded {
+            Some(assets) => Source::Embedded(assets),
+            None => Source::Dir(config.target.clone()),
+        };
+
+        #[cfg(not(feature = "embed"))]
+        let source = Source::Dir(config.target.clone());
+
+        Self {
+            inner: Arc::new(Inner {
+                source,
+                digests: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// The `integrity` value of the file at `path`, like `sha384-...`. The path is relative to
+    /// `target`, with or without a leading `/`. Returns `None` if the file does not exist.
+    pub fn get(&self, path: &str) -> Option<String> {
+        let path = path.trim_start_matches('/');
+
+        if path.split('/').any(|segment| segment == "..") {
+            return None;
+        }
+
+        if let Some(digest) = self.inner.digests.lock().unwrap().get(path) {
+            return Some(digest.clone());
+        }
+
+        let digest = match &self.inner.source {
+            Source::Dir(target) => digest(&std::fs::read(target.join(path)).ok()?),
+            #[cfg(feature = "embed")]
+            Source::Embedded(assets) => digest(assets.get(path)?.contents),
+        };
+
+        self.inner
+            .digests
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), digest.clone());
+
+        Some(digest)
+    }
+
+    /// Compute the digests of the scripts and stylesheets in `target` ahead of time, so they do
+    /// not have to be read while HTML is being served.
+    pub(crate) fn warm_up(&self) {
+        // Embedded files are in memory already.
+        let Some(target) = self.inner.source.dir() else {
+            return;
+        };
+
+        let files = WalkDir::new(target)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| {
+                entry
+                    .path()
+                    .extension()
+                    .is_some_and(|extension| ["js", "mjs", "css"].iter().any(|e| extension == *e))
+            })
+            .filter_map(|entry| relative_path(target, entry.path()));
+
+        for file in files {
+            self.get(&file);
+        }
+    }
+}
+
+fn relative_path(target: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(target).ok()?;
+
+    Some(
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+fn digest(contents: &[u8]) -> String {
+    format!("sha384-{}", STANDARD.encode(Sha384::digest(contents)))
+}
+
+/// Add `integrity` to the `<script src>` and `<link rel=stylesheet>` tags of the document at
+/// `uri` that reference files in `target`, which is mounted at `base`. Tags that already have one
+/// are kept as they are.
+pub(crate) fn add_integrity(integrity: &Integrity, base: &str, uri: &Uri, html: &mut HtmlRewrite) {
+    for (selector, attribute) in [
+        ("script[src]:not([integrity])", "src"),
+        ("link[rel~=stylesheet][href]:not([integrity])", "href"),
+    ] {
+        let integrity = integrity.clone();
+        let document = uri.path().to_owned();
+        let base = base.trim_start_matches('/').to_owned();
+
+        html.element(selector, move |element| {
+            let digest = element
+                .get_attribute(attribute)
+                .and_then(|url| local_path(&document, &url))
+                .and_then(|path| integrity.get(path.strip_prefix(&base)?));
+
+            if let Some(digest) = digest {
+                element.set_attribute("integrity", &digest)?;
+            }
+
+            Ok(())
+        });
+    }
+}
+
+/// The path relative to `target` that `url` refers to from the document at `document`. URLs of
+/// other origins are ignored.
+fn local_path(document: &str, url: &str) -> Option<String> {
+    let has_scheme = url
+        .split(['/', '?', '#'])
+        .next()
+        .is_some_and(|first| first.contains(':'));
+
+    if url.starts_with("//") || has_scheme {
+        return None;
+    }
+
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+
+    let path = if url.starts_with('/') {
+        url.to_owned()
+    } else {
+        let directory = &document[..document.rfind('/').map_or(0, |index| index + 1)];
+
+        format!("{directory}{url}")
+    };
+
+    let mut segments = Vec::new();
+
+    for segment in percent_decode_str(&path).decode_utf8().ok()?.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment.to_owned()),
+        }
+    }
+
+    Some(segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_absolute_paths() {
+        assert_eq!(
+            local_path("/", "/assets/index-BkA0k5xJ.js").as_deref(),
+            Some("assets/index-BkA0k5xJ.js")
+        );
+        assert_eq!(
+            local_path("/app/docs/", "/app/assets/index-B_xk-9aQ.css?v=1#top").as_deref(),
+            Some("app/assets/index-B_xk-9aQ.css")
+        );
+    }
+
+    #[test]
+    fn resolves_relative_paths() {
+        // `base: "./"` in Vite
+        assert_eq!(
+            local_path("/index.html", "./assets/index-BkA0k5xJ.js").as_deref(),
+            Some("assets/index-BkA0k5xJ.js")
+        );
+        assert_eq!(
+            local_path("/docs/getting-started/", "../../_astro/Layout.Dk2qV7Ws.css").as_deref(),
+            Some("_astro/Layout.Dk2qV7Ws.css")
+        );
+        assert_eq!(
+            local_path("/docs/intro", "static/js/main.3f2a1b9c.chunk.js").as_deref(),
+            Some("docs/static/js/main.3f2a1b9c.chunk.js")
+        );
+        assert_eq!(
+            local_path("/", "assets/release%20notes.js").as_deref(),
+            Some("assets/release notes.js")
+        );
+    }
+
+    #[test]
+    fn ignores_other_origins() {
+        assert_eq!(
+            local_path("/", "https://unpkg.com/react@18/umd/react.js"),
+            None
+        );
+        assert_eq!(local_path("/", "//cdn.jsdelivr.net/npm/vue@3"), None);
+        assert_eq!(local_path("/", "data:text/javascript,console.log(1)"), None);
+    }
+
+    #[test]
+    fn rejects_paths_above_the_root() {
+        assert_eq!(local_path("/index.html", "../secret.js"), None);
+        assert_eq!(local_path("/", "/assets/../../secret.js"), None);
+    }
+}
@@ -1,4 +1,42 @@
+mod base_path;
+mod body;
+#[cfg(feature = "build")]
+pub mod build;
+mod caching;
+#[cfg(feature = "build")]
+mod compress;
+mod csp;
+mod discovery;
+#[cfg(feature = "embed")]
+mod embed;
+mod error;
+mod fingerprint;
+mod integrity;
+mod manifest;
+mod package_manager;
+mod readiness;
+mod routing;
+mod spa;
+mod supervisor;
+mod transform;
 mod webdev_service;
 
-pub use webdev_service::*;
+pub use body::WebdevBody;
+pub use caching::{CacheOverride, CachePolicy};
+pub use csp::{ContentSecurityPolicy, CspDirective, CspNonce};
+#[cfg(feature = "embed")]
+pub use embed::{EmbeddedAssets, EmbeddedFile};
+pub use error::WebdevError;
+#[cfg(feature = "https")]
+pub use insecure_reverse_proxy::HttpsOptions;
+pub use insecure_reverse_proxy::{ClientIpExtractor, ForwardedHeaders, PeerAddr};
+pub use integrity::Integrity;
+pub use manifest::{EntryAssets, Manifest, ManifestFormat};
+pub use package_manager::PackageManager;
+pub use readiness::Readiness;
+pub use routing::{Routing, TrailingSlash};
+pub use spa::SpaFallback;
+pub use supervisor::{DevServerState, RestartPolicy, ShutdownHandle};
+pub use transform::{HtmlBody, HtmlRewrite, HtmlTransform};
 
+pub use webdev_service::*;
@@ -0,0 +1,509 @@
+//! Resolving the built assets of an entry point, for handlers that render HTML themselves.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{base_path, transform::escape, Config, Integrity, Mode, WebdevError};
+
+/// The format of the manifest the bundler writes to `target`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ManifestFormat {
+    /// `.vite/manifest.json`, or `manifest.json` before Vite 5, written with `build.manifest`.
+    #[default]
+    Vite,
+    /// `manifest.json` written by webpack-manifest-plugin.
+    Webpack,
+}
+
+/// Maps the entry points of the frontend to the files that have to be included in the HTML.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    inner: Inner,
+    /// Set in [`Mode::Production`] if [`Config::subresource_integrity`] is enabled.
+    integrity: Option<Integrity>,
+    /// The base path the frontend is mounted at, with a trailing slash.
+    base: String,
+}
+
+#[derive(Debug, Clone)]
+enum Inner {
+    Vite(HashMap<String, ViteChunk>),
+    Webpack(HashMap<String, String>),
+    /// The Vite dev server serves the source modules itself.
+    ViteDev,
+    /// The webpack dev server serves each entry as `{entry}.js`.
+    WebpackDev,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ViteChunk {
+    file: String,
+    #[serde(default)]
+    css: Vec<String>,
+    #[serde(default)]
+    imports: Vec<String>,
+}
+
+/// The files an entry point needs, as URLs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryAssets {
+    pub scripts: Vec<String>,
+    pub styles: Vec<String>,
+    /// Chunks imported by the scripts, which can be fetched in parallel with them.
+    pub preloads: Vec<String>,
+    /// Whether the scripts are ES modules.
+    pub module: bool,
+    /// The `integrity` values of the URLs above, if [`Config::subresource_integrity`] is
+    /// enabled.
+    pub integrity: HashMap<String, String>,
+}
+
+impl Manifest {
+    /// Load the manifest from `target`, or from the [`Config::embedded`] assets, in
+    /// [`Mode::Production`]. In [`Mode::Development`] the entries resolve to the URLs the dev
+    /// server serves them at instead.
+    ///
+    /// With [`Config::subresource_integrity`] enabled, production entries include the digests of
+    /// their files.
+    pub fn load(config: &Config, format: ManifestFormat) -> Result<Self, WebdevError> {
+        let inner = match (&config.mode, format) {
+            (Mode::Development, ManifestFormat::Vite) => Inner::ViteDev,
+            (Mode::Development, ManifestFormat::Webpack) => Inner::WebpackDev,
+            (Mode::Production, ManifestFormat::Vite) => {
+                // Vite 5 moved the manifest into `.vite`.
+                match read(config, ".vite/manifest.json") {
+                    Err(WebdevError::MissingManifest { .. }) => {
+                        Inner::Vite(read(config, "manifest.json")?)
+                    }
+                    result => Inner::Vite(result?),
+                }
+            }
+            (Mode::Production, ManifestFormat::Webpack) => {
+                Inner::Webpack(read(config, "manifest.json")?)
+            }
+        };
+
+        let integrity = (matches!(config.mode, Mode::Production) && config.subresource_integrity)
+            .then(|| Integrity::new(config));
+
+        Ok(Self {
+            inner,
+            integrity,
+            base: base_path::normalize(&config.base_path),
+        })
+    }
+
+    /// Resolve an entry point, like `src/main.ts` for Vite or `main` for webpack. Returns `None`
+    /// if the manifest does not contain it.
+    pub fn resolve(&self, entry: &str) -> Option<EntryAssets> {
+        let mut assets = self.resolve_urls(entry)?;
+
+        if let Some(integrity) = &self.integrity {
+            assets.integrity = assets
+                .urls()
+                .filter_map(|url| {
+                    let digest = integrity.get(url.strip_prefix(&self.base)?)?;
+
+                    Some((url.clone(), digest))
+                })
+                .collect();
+        }
+
+        Some(assets)
+    }
+
+    fn resolve_urls(&self, entry: &str) -> Option<EntryAssets> {
+        let base = &self.base;
+
+        match &self.inner {
+            Inner::Vite(chunks) => resolve_vite(chunks, entry, base),
+            Inner::Webpack(files) => {
+                let scripts = files
+                    .get(&format!("{entry}.js"))
+                    .map(|file| vec![url(base, file)])
+                    .unwrap_or_default();
+                let styles = files
+                    .get(&format!("{entry}.css"))
+                    .map(|file| vec![url(base, file)])
+                    .unwrap_or_default();
+
+                if scripts.is_empty() && styles.is_empty() {
+                    return None;
+                }
+
+                Some(EntryAssets {
+                    scripts,
+                    styles,
+                    ..Default::default()
+                })
+            }
+            Inner::ViteDev => Some(EntryAssets {
+                scripts: vec![url(base, "@vite/client"), url(base, entry)],
+                module: true,
+                ..Default::default()
+            }),
+            Inner::WebpackDev => Some(EntryAssets {
+                scripts: vec![url(base, &format!("{entry}.js"))],
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Render the tags for an entry point. See [`EntryAssets::tags`].
+    pub fn tags(&self, entry: &str) -> Option<String> {
+        self.resolve(entry).map(|assets| assets.tags())
+    }
+}
+
+impl EntryAssets {
+    /// Render `<link>` tags for the styles and preloads, and `<script>` tags for the scripts.
+    pub fn tags(&self) -> String {
+        let mut html = String::new();
+
+        for style in &self.styles {
+            html.push_str(&format!(
+                "<link rel=\"stylesheet\" href=\"{}\"{}>\n",
+                escape(style),
+                self.integrity_attribute(style)
+            ));
+        }
+
+        for script in &self.scripts {
+            let integrity = self.integrity_attribute(script);
+            let script = escape(script);
+
+            if self.module {
+                html.push_str(&format!(
+                    "<script type=\"module\" src=\"{script}\"{integrity}></script>\n"
+                ));
+            } else {
+                html.push_str(&format!(
+                    "<script defer src=\"{script}\"{integrity}></script>\n"
+                ));
+            }
+        }
+
+        for preload in &self.preloads {
+            html.push_str(&format!(
+                "<link rel=\"modulepreload\" href=\"{}\"{}>\n",
+                escape(preload),
+                self.integrity_attribute(preload)
+            ));
+        }
+
+        html
+    }
+
+    fn urls(&self) -> impl Iterator<Item = &String> {
+        self.styles
+            .iter()
+            .chain(&self.scripts)
+            .chain(&self.preloads)
+    }
+
+    fn integrity_attribute(&self, url: &str) -> String {
+        self.integrity
+            .get(url)
+            .map(|digest| format!(" integrity=\"{digest}\""))
+            .unwrap_or_default()
+    }
+}
+
+/// Read the manifest at `file`, relative to `target`.
+fn read<T>(config: &Config, file: &str) -> Result<T, WebdevError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let path = config.target.join(file);
+
+    let contents = read_file(config, file).map_err(|source| WebdevError::MissingManifest {
+        path: path.clone(),
+        source,
+    })?;
+
+    serde_json::from_slice(&contents)
+        .map_err(|source| WebdevError::InvalidManifest { path, source })
+}
+
+fn read_file(config: &Config, file: &str) -> std::io::Result<Cow<'static, [u8]>> {
+    #[cfg(feature = "embed")]
+    if let Some(assets) = config.embedded {
+        return match assets.get(file) {
+            Some(file) => Ok(Cow::Borrowed(file.contents)),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        };
+    }
+
+    std::fs::read(config.target.join(file)).map(Cow::Owned)
+}
+
+/// Collect the entry's files like Vite's backend integration guide does: the entry's CSS first,
+/// then the CSS and files of the chunks it imports, in the order they are imported.
+fn resolve_vite(
+    chunks: &HashMap<String, ViteChunk>,
+    entry: &str,
+    base: &str,
+) -> Option<EntryAssets> {
+    let chunk = chunks.get(entry)?;
+
+    let mut seen = HashSet::new();
+    let mut imported = Vec::new();
+    imported_chunks(chunks, chunk, &mut seen, &mut imported);
+
+    let mut styles = chunk
+        .css
+        .iter()
+        .map(|css| url(base, css))
+        .collect::<Vec<_>>();
+
+    for css in imported.iter().flat_map(|chunk| &chunk.css) {
+        let css = url(base, css);
+
+        if !styles.contains(&css) {
+            styles.push(css);
+        }
+    }
+
+    Some(EntryAssets {
+        scripts: vec![url(base, &chunk.file)],
+        styles,
+        preloads: imported
+            .iter()
+            .map(|chunk| url(base, &chunk.file))
+            .collect(),
+        module: true,
+        ..Default::default()
+    })
+}
+
+fn imported_chunks<'a>(
+    chunks: &'a HashMap<String, ViteChunk>,
+    chunk: &'a ViteChunk,
+    seen: &mut HashSet<&'a str>,
+    imported: &mut Vec<&'a ViteChunk>,
+) {
+    for import in &chunk.imports {
+        let Some(importee) = chunks.get(import) else {
+            continue;
+        };
+
+        if !seen.insert(import) {
+            continue;
+        }
+
+        imported_chunks(chunks, importee, seen, imported);
+        imported.push(importee);
+    }
+}
+
+/// The URL of a file relative to `target`, which is served at `base`. Absolute paths and URLs,
+/// which webpack writes when `publicPath` is set, are kept.
+fn url(base: &str, file: &str) -> String {
+    if file.starts_with('/') || file.contains("://") {
+        file.to_owned()
+    } else {
+        format!("{base}{file}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// From Vite's backend integration guide, with `shared` importing a vendor chunk that has
+    /// CSS of its own.
+    const VITE_MANIFEST: &str = r#"{
+  "_shared-B7PI925R.js": {
+    "file": "assets/shared-B7PI925R.js",
+    "name": "shared",
+    "imports": ["_vendor-Dq3_-xYz.js"],
+    "css": ["assets/shared-ChJ_j-JJ.css"]
+  },
+  "_vendor-Dq3_-xYz.js": {
+    "file": "assets/vendor-Dq3_-xYz.js",
+    "name": "vendor",
+    "css": ["assets/vendor-C9kFq2Lb.css"]
+  },
+  "baz.js": {
+    "file": "assets/baz-B2H3sXNv.js",
+    "name": "baz",
+    "src": "baz.js",
+    "isDynamicEntry": true
+  },
+  "views/bar.js": {
+    "file": "assets/bar-gkvgaI9m.js",
+    "name": "bar",
+    "src": "views/bar.js",
+    "isEntry": true,
+    "imports": ["_shared-B7PI925R.js", "_vendor-Dq3_-xYz.js"],
+    "dynamicImports": ["baz.js"]
+  },
+  "views/foo.js": {
+    "file": "assets/foo-BRBmoGS9.js",
+    "name": "foo",
+    "src": "views/foo.js",
+    "isEntry": true,
+    "imports": ["_shared-B7PI925R.js"],
+    "css": ["assets/foo-5UjPuW-k.css", "assets/shared-ChJ_j-JJ.css"]
+  }
+}"#;
+
+    const WEBPACK_MANIFEST: &str = r#"{
+  "main.js": "static/js/main.3f2a1b9c.js",
+  "main.css": "static/css/main.8d1e0c2a.css",
+  "admin.js": "https://cdn.example.com/admin.5b7e9f01.js",
+  "index.html": "index.html"
+}"#;
+
+    fn vite_chunks() -> HashMap<String, ViteChunk> {
+        serde_json::from_str(VITE_MANIFEST).unwrap()
+    }
+
+    fn load(mode: Mode, format: ManifestFormat, files: &[(&str, &str)]) -> Manifest {
+        let root = tempfile::tempdir().unwrap();
+
+        for (file, contents) in files {
+            let path = root.path().join("dist").join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        let config = Config::new_npm(mode, root.path()).base_path("/app");
+
+        Manifest::load(&config, format).unwrap()
+    }
+
+    #[test]
+    fn collects_css_of_the_entry_first_without_duplicates() {
+        let assets = resolve_vite(&vite_chunks(), "views/foo.js", "/").unwrap();
+
+        assert_eq!(assets.scripts, ["/assets/foo-BRBmoGS9.js"]);
+        assert_eq!(
+            assets.styles,
+            [
+                "/assets/foo-5UjPuW-k.css",
+                "/assets/shared-ChJ_j-JJ.css",
+                "/assets/vendor-C9kFq2Lb.css",
+            ]
+        );
+        assert!(assets.module);
+    }
+
+    #[test]
+    fn follows_imports_recursively_once() {
+        let assets = resolve_vite(&vite_chunks(), "views/bar.js", "/").unwrap();
+
+        assert_eq!(assets.scripts, ["/assets/bar-gkvgaI9m.js"]);
+        assert_eq!(
+            assets.styles,
+            ["/assets/vendor-C9kFq2Lb.css", "/assets/shared-ChJ_j-JJ.css"]
+        );
+        // Dynamic imports are loaded on demand.
+        assert_eq!(
+            assets.preloads,
+            ["/assets/vendor-Dq3_-xYz.js", "/assets/shared-B7PI925R.js"]
+        );
+    }
+
+    #[test]
+    fn resolves_below_the_base_path() {
+        let assets = resolve_vite(&vite_chunks(), "views/foo.js", "/app/").unwrap();
+
+        assert_eq!(assets.scripts, ["/app/assets/foo-BRBmoGS9.js"]);
+        assert_eq!(assets.preloads[0], "/app/assets/vendor-Dq3_-xYz.js");
+        assert!(resolve_vite(&vite_chunks(), "views/missing.js", "/app/").is_none());
+    }
+
+    #[test]
+    fn loads_the_vite_manifest_before_vite_5() {
+        let manifest = load(
+            Mode::Production,
+            ManifestFormat::Vite,
+            &[("manifest.json", VITE_MANIFEST)],
+        );
+
+        assert_eq!(
+            manifest.resolve("views/bar.js").unwrap().scripts,
+            ["/app/assets/bar-gkvgaI9m.js"]
+        );
+    }
+
+    #[test]
+    fn looks_up_webpack_entries() {
+        let manifest = load(
+            Mode::Production,
+            ManifestFormat::Webpack,
+            &[("manifest.json", WEBPACK_MANIFEST)],
+        );
+
+        let main = manifest.resolve("main").unwrap();
+        assert_eq!(main.scripts, ["/app/static/js/main.3f2a1b9c.js"]);
+        assert_eq!(main.styles, ["/app/static/css/main.8d1e0c2a.css"]);
+        assert!(!main.module);
+
+        // `publicPath` URLs are kept as they are.
+        let admin = manifest.resolve("admin").unwrap();
+        assert_eq!(admin.scripts, ["https://cdn.example.com/admin.5b7e9f01.js"]);
+        assert!(admin.styles.is_empty());
+
+        assert!(manifest.resolve("missing").is_none());
+    }
+
+    #[test]
+    fn fails_without_a_manifest() {
+        let root = tempfile::tempdir().unwrap();
+        let config = Config::new_npm(Mode::Production, root.path());
+
+        assert!(matches!(
+            Manifest::load(&config, ManifestFormat::Vite),
+            Err(WebdevError::MissingManifest { .. })
+        ));
+    }
+
+    #[test]
+    fn resolves_dev_server_urls_below_the_base_path() {
+        let vite = load(Mode::Development, ManifestFormat::Vite, &[]);
+        let assets = vite.resolve("src/main.ts").unwrap();
+
+        assert_eq!(assets.scripts, ["/app/@vite/client", "/app/src/main.ts"]);
+        assert!(assets.module);
+        assert_eq!(
+            assets.tags(),
+            "<script type=\"module\" src=\"/app/@vite/client\"></script>\n\
+             <script type=\"module\" src=\"/app/src/main.ts\"></script>\n"
+        );
+
+        let webpack = load(Mode::Development, ManifestFormat::Webpack, &[]);
+
+        assert_eq!(webpack.resolve("main").unwrap().scripts, ["/app/main.js"]);
+    }
+
+    #[cfg(feature = "embed")]
+    #[test]
+    fn reads_the_embedded_manifest() {
+        use crate::{EmbeddedAssets, EmbeddedFile};
+
+        static ASSETS: EmbeddedAssets = EmbeddedAssets::new(&[EmbeddedFile {
+            path: ".vite/manifest.json",
+            content_type: "application/json",
+            etag: "\"5f2b18c3e1f0a42d\"",
+            contents: VITE_MANIFEST.as_bytes(),
+            gzip: None,
+            brotli: None,
+            zstd: None,
+        }]);
+
+        // Nothing is shipped next to the binary.
+        let config = Config::new_npm(Mode::Production, "/nonexistent").embedded(&ASSETS);
+        let manifest = Manifest::load(&config, ManifestFormat::Vite).unwrap();
+
+        assert_eq!(
+            manifest.resolve("views/foo.js").unwrap().scripts,
+            ["/assets/foo-BRBmoGS9.js"]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// The JavaScript package manager used to install dependencies and run scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PackageManager {
+    Npm,
+    Pnpm,
+    /// Yarn 1.x.
+    YarnClassic,
+    /// Yarn 2+.
+    YarnBerry,
+    Bun,
+    Deno,
+}
+
+impl PackageManager {
+    /// Detect the package manager from the lockfile in `root`.
+    pub fn detect(root: &Path) -> Option<Self> {
+        if root.join("pnpm-lock.yaml").exists() {
+            return Some(Self::Pnpm);
+        }
+
+        if root.join("bun.lockb").exists() || root.join("bun.lock").exists() {
+            return Some(Self::Bun);
+        }
+
+        if root.join("yarn.lock").exists() {
+            return Some(Self::detect_yarn(root));
+        }
+
+        if root.join("package-lock.json").exists() {
+            return Some(Self::Npm);
+        }
+
+        if root.join("deno.lock").exists() {
+            return Some(Self::Deno);
+        }
+
+        None
+    }
+
+    /// Tell Yarn 1.x and Yarn 2+ apart by their lockfile format and config file.
+    pub(crate) fn detect_yarn(root: &Path) -> Self {
+        let berry_lockfile = std::fs::read_to_string(root.join("yarn.lock"))
+            .is_ok_and(|lockfile| lockfile.contains("__metadata:"));
+
+        if berry_lockfile || root.join(".yarnrc.yml").exists() {
+            Self::YarnBerry
+        } else {
+            Self::YarnClassic
+        }
+    }
+
+    /// The executable that is expected to be in `$PATH`.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::Npm => "npm",
+            Self::Pnpm => "pnpm",
+            Self::YarnClassic | Self::YarnBerry => "yarn",
+            Self::Bun => "bun",
+            Self::Deno => "deno",
+        }
+    }
+
+    /// The lockfiles this package manager reads and writes.
+    pub fn lockfiles(&self) -> &'static [&'static str] {
+        match self {
+            Self::Npm => &["package-lock.json", "npm-shrinkwrap.json"],
+            Self::Pnpm => &["pnpm-lock.yaml"],
+            Self::YarnClassic | Self::YarnBerry => &["yarn.lock"],
+            Self::Bun => &["bun.lockb", "bun.lock"],
+            Self::Deno => &["deno.lock", "deno.json"],
+        }
+    }
+
+    /// Arguments for `install_command`. With `frozen` the install fails instead of updating an
+    /// outdated lockfile.
+    pub(crate) fn install_args(&self, install_command: &str, frozen: bool) -> Vec<String> {
+        if !frozen {
+            return vec![install_command.to_owned()];
+        }
+
+        match self {
+            Self::Npm if install_command == "install" => vec!["ci".to_owned()],
+            Self::Npm => vec![install_command.to_owned()],
+            Self::Pnpm | Self::YarnClassic | Self::Bun => {
+                vec![install_command.to_owned(), "--frozen-lockfile".to_owned()]
+            }
+            Self::YarnBerry => vec![install_command.to_owned(), "--immutable".to_owned()],
+            Self::Deno => vec![install_command.to_owned(), "--frozen".to_owned()],
+        }
+    }
+
+    /// Arguments that run the package.json script (or deno task) `script` with `extra` arguments.
+    pub(crate) fn run_args(&self, script: &str, extra: &[String]) -> Vec<String> {
+        let mut args = match self {
+            Self::Deno => vec!["task".to_owned(), script.to_owned()],
+            _ => vec!["run".to_owned(), script.to_owned()],
+        };
+
+        if !extra.is_empty() {
+            // npm only forwards arguments to the script after a separator.
+            if let Self::Npm = self {
+                args.push("--".to_owned());
+            }
+
+            args.extend(extra.iter().cloned());
+        }
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+
+        for (name, contents) in files {
+            std::fs::write(root.path().join(name), contents).unwrap();
+        }
+
+        root
+    }
+
+    fn detect(files: &[(&str, &str)]) -> Option<PackageManager> {
+        PackageManager::detect(project(files).path())
+    }
+
+    #[test]
+    fn install_args() {
+        use PackageManager::*;
+
+        for (package_manager, frozen) in [
+            (Npm, &["ci"][..]),
+            (Pnpm, &["install", "--frozen-lockfile"]),
+            (YarnClassic, &["install", "--frozen-lockfile"]),
+            (YarnBerry, &["install", "--immutable"]),
+            (Bun, &["install", "--frozen-lockfile"]),
+            (Deno, &["install", "--frozen"]),
+        ] {
+            assert_eq!(package_manager.install_args("install", true), frozen);
+            assert_eq!(package_manager.install_args("install", false), ["install"]);
+        }
+
+        // Only `npm install` has a frozen counterpart.
+        assert_eq!(Npm.install_args("add", true), ["add"]);
+        assert_eq!(Pnpm.install_args("add", true), ["add", "--frozen-lockfile"]);
+    }
+
+    #[test]
+    fn run_args() {
+        let extra = ["--port".to_owned(), "3000".to_owned()];
+
+        assert_eq!(PackageManager::Npm.run_args("dev", &[]), ["run", "dev"]);
+        assert_eq!(
+            PackageManager::Npm.run_args("dev", &extra),
+            ["run", "dev", "--", "--port", "3000"]
+        );
+        assert_eq!(
+            PackageManager::Pnpm.run_args("dev", &extra),
+            ["run", "dev", "--port", "3000"]
+        );
+        assert_eq!(
+            PackageManager::Deno.run_args("dev", &extra),
+            ["task", "dev", "--port", "3000"]
+        );
+    }
+
+    #[test]
+    fn detects_lockfiles_in_order() {
+        assert_eq!(detect(&[]), None);
+        assert_eq!(detect(&[("package.json", "{}")]), None);
+        assert_eq!(
+            detect(&[("package-lock.json", "")]),
+            Some(PackageManager::Npm)
+        );
+        assert_eq!(detect(&[("bun.lock", "")]), Some(PackageManager::Bun));
+        assert_eq!(detect(&[("deno.lock", "")]), Some(PackageManager::Deno));
+
+        assert_eq!(
+            detect(&[("package-lock.json", ""), ("pnpm-lock.yaml", "")]),
+            Some(PackageManager::Pnpm)
+        );
+        assert_eq!(
+            detect(&[("yarn.lock", ""), ("bun.lockb", "")]),
+            Some(PackageManager::Bun)
+        );
+        assert_eq!(
+            detect(&[("package-lock.json", ""), ("yarn.lock", "")]),
+            Some(PackageManager::YarnClassic)
+        );
+        assert_eq!(
+            detect(&[("deno.lock", ""), ("package-lock.json", "")]),
+            Some(PackageManager::Npm)
+        );
+    }
+
+    #[test]
+    fn detects_yarn_berry() {
+        let classic = "# yarn lockfile v1\n\nleft-pad@^1.3.0:\n  version \"1.3.0\"\n";
+        let berry = "__metadata:\n  version: 8\n  cacheKey: 10c0\n";
+
+        assert_eq!(
+            detect(&[("yarn.lock", classic)]),
+            Some(PackageManager::YarnClassic)
+        );
+        assert_eq!(
+            detect(&[("yarn.lock", berry)]),
+            Some(PackageManager::YarnBerry)
+        );
+        assert_eq!(
+            detect(&[
+                ("yarn.lock", classic),
+                (".yarnrc.yml", "nodeLinker: node-modules\n")
+            ]),
+            Some(PackageManager::YarnBerry)
+        );
+        // Before the first install there is only the config file.
+        assert_eq!(
+            PackageManager::detect_yarn(project(&[(".yarnrc.yml", "")]).path()),
+            PackageManager::YarnBerry
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::{path::Path, time::Duration};
+
+use bytes::Bytes;
+use http::{Request, Uri};
+use http_body_util::Empty;
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+#[cfg(feature = "https")]
+use insecure_reverse_proxy::HttpsOptions;
+#[cfg(unix)]
+use insecure_reverse_proxy::UnixConnector;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+
+/// Controls how the dev server is probed before requests are forwarded to it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Readiness {
+    /// Path that has to respond successfully for the dev server to be considered ready. If
+    /// unset, the dev server is ready as soon as it accepts connections.
+    pub health_path: Option<String>,
+    /// Delay between probes.
+    pub probe_interval: Duration,
+    /// How long a request waits for the dev server to become ready before it is answered with
+    /// `503 Service Unavailable`.
+    pub request_timeout: Duration,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self {
+            health_path: None,
+            probe_interval: Duration::from_millis(250),
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// How the dev server is connected to, besides its URL.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Connection<'a> {
+    Tcp,
+    /// The Unix domain socket the dev server listens on.
+    Unix(&'a Path),
+    /// HTTPS, verified according to the options.
+    #[cfg(feature = "https")]
+    Https(&'a HttpsOptions),
+}
+
+impl Readiness {
+    pub fn health_path(mut self, value: impl Into<String>) -> Self {
+        self.health_path = Some(value.into());
+
+        self
+    }
+
+    /// Resolve once the dev server at `target`, reached over `connection`, is ready.
+    pub(crate) async fn probe(&self, target: String, connection: Connection<'_>) {
+        let Ok(target) = target.parse::<Uri>() else {
+            tracing::error!("cannot probe invalid dev server url {}", target);
+
+            return std::future::pending().await;
+        };
+
+        loop {
+            if self.check(&target, connection).await {
+                return;
+            }
+
+            tokio::time::sleep(self.probe_interval).await;
+        }
+    }
+
+    async fn check(&self, target: &Uri, connection: Connection<'_>) -> bool {
+        let Some(path) = &self.health_path else {
+            #[cfg(unix)]
+            if let Connection::Unix(socket) = connection {
+                return tokio::net::UnixStream::connect(socket).await.is_ok();
+            }
+
+            let host = target.host().unwrap_or("localhost");
+            let port = target
+                .port_u16()
+                .unwrap_or(if target.scheme_str() == Some("https") {
+                    443
+                } else {
+                    80
+                });
+
+            return TcpStream::connect((host, port)).await.is_ok();
+        };
+
+        let Ok(uri) =
+            format!("{}{}", target.to_string().trim_end_matches('/'), path).parse::<Uri>()
+        else {
+            tracing::error!("invalid health path {}", path);
+
+            return false;
+        };
+
+        let request = Request::get(uri)
+            .body(Empty::<Bytes>::new())
+            .expect("request is valid");
+
+        let response = match connection {
+            #[cfg(unix)]
+            Connection::Unix(socket) => {
+                Client::builder(TokioExecutor::new())
+                    .build(UnixConnector::new(socket))
+                    .request(request)
+                    .await
+            }
+            #[cfg(feature = "https")]
+            Connection::Https(options) => {
+                let connector = match options.connector() {
+                    Ok(connector) => connector,
+                    Err(error) => {
+                        tracing::error!("cannot probe the dev server: {}", error);
+
+                        return false;
+                    }
+                };
+
+                Client::builder(TokioExecutor::new())
+                    .build(connector)
+                    .request(request)
+                    .await
+            }
+            _ => {
+                Client::builder(TokioExecutor::new())
+                    .build_http()
+                    .request(request)
+                    .await
+            }
+        };
+
+        match response {
+            Ok(response) => {
+                tracing::debug!("health check responded with {}", response.status());
+
+                response.status().is_success() || response.status().is_redirection()
+            }
+            Err(error) => {
+                tracing::debug!("health check failed: {}", error);
+
+                false
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use axum::{routing::get, Router};
+    use tokio::net::UnixListener;
+
+    use super::*;
+
+    fn readiness() -> Readiness {
+        Readiness {
+            probe_interval: Duration::from_millis(20),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn probes_a_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("dev.sock");
+
+        let readiness = readiness();
+        let probe = readiness.probe("http://localhost".into(), Connection::Unix(&socket));
+        tokio::pin!(probe);
+
+        // The dev server has not started listening yet.
+        assert!(tokio::time::timeout(Duration::from_millis(100), &mut probe)
+            .await
+            .is_err());
+
+        let _listener = UnixListener::bind(&socket).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), probe)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn probes_the_health_path_over_a_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("dev.sock");
+
+        let listener = UnixListener::bind(&socket).unwrap();
+        let app = Router::new().route("/ready", get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let ready = readiness().health_path("/ready");
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            ready.probe("http://localhost".into(), Connection::Unix(&socket)),
+        )
+        .await
+        .unwrap();
+
+        let missing = readiness().health_path("/missing");
+        assert!(tokio::time::timeout(
+            Duration::from_millis(200),
+            missing.probe("http://localhost".into(), Connection::Unix(&socket)),
+        )
+        .await
+        .is_err());
+    }
+}
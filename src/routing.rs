@@ -0,0 +1,343 @@
+use std::path::Path;
+
+use http::{header, Request, Response, StatusCode, Uri};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Serialize};
+
+use crate::WebdevBody;
+
+/// Characters that are percent-encoded in a path segment of a rewritten request.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Controls how request paths are resolved to the files of the production build.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Routing {
+    /// Serve `about.html` for `/about`, as output by multi-page app frameworks like Astro and
+    /// VitePress.
+    pub clean_urls: bool,
+    /// Which form of a page's path is canonical. The other form is redirected to it.
+    pub trailing_slash: TrailingSlash,
+    /// File, relative to `target`, that is served with `404 Not Found` when a path does not
+    /// resolve to a file, e.g. `404.html`.
+    pub not_found_page: Option<String>,
+}
+
+/// Whether the path of a page ends with a slash.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TrailingSlash {
+    /// Redirect `/about` to `/about/`.
+    #[default]
+    Always,
+    /// Redirect `/about/` to `/about`.
+    Never,
+    /// Serve pages with and without a trailing slash.
+    Ignore,
+}
+
+/// What a request path resolved to.
+pub(crate) enum Route {
+    /// Serve the file at this path, relative to `target`.
+    File(String),
+    /// Redirect to the canonical form of the path.
+    Redirect {
+        trailing_slash: bool,
+    },
+    NotFound,
+}
+
+impl Routing {
+    pub fn clean_urls(mut self, value: bool) -> Self {
+        self.clean_urls = value;
+
+        self
+    }
+
+    pub fn trailing_slash(mut self, value: TrailingSlash) -> Self {
+        self.trailing_slash = value;
+
+        self
+    }
+
+    pub fn not_found_page(mut self, value: impl Into<String>) -> Self {
+        self.not_found_page = Some(value.into());
+
+        self
+    }
+
+    /// Resolve the percent-decoded `path` of a request, using `is_file` to check whether a path
+    /// relative to `target` exists.
+    pub(crate) fn resolve(&self, path: &str, is_file: impl Fn(&str) -> bool) -> Route {
+        let path = path.trim_start_matches('/');
+
+        if path.split('/').any(|segment| segment == "..") {
+            return Route::NotFound;
+        }
+
+        if path.is_empty() {
+            if is_file("index.html") {
+                return Route::File("index.html".into());
+            }
+
+            return Route::NotFound;
+        }
+
+        let (name, has_trailing_slash) = match path.strip_suffix('/') {
+            Some(name) => (name, true),
+            None => (path, false),
+        };
+
+        if !has_trailing_slash && is_file(name) {
+            return Route::File(name.into());
+        }
+
+        let index = Some(format!("{name}/index.html"));
+        let page = self.clean_urls.then(|| format!("{name}.html"));
+
+        let candidates = if has_trailing_slash {
+            [index, page]
+        } else {
+            [page, index]
+        };
+
+        let Some(file) = candidates.into_iter().flatten().find(|file| is_file(file)) else {
+            return Route::NotFound;
+        };
+
+        match (self.trailing_slash, has_trailing_slash) {
+            (TrailingSlash::Always, false) => Route::Redirect {
+                trailing_slash: true,
+            },
+            (TrailingSlash::Never, true) => Route::Redirect {
+                trailing_slash: false,
+            },
+            _ => Route::File(file),
+        }
+    }
+
+    /// Like [`Self::resolve`], checking for files in `target` on a blocking thread.
+    pub(crate) async fn resolve_on_disk(&self, target: &Path, uri: &Uri) -> Route {
+        let Ok(path) = percent_decode_str(uri.path()).decode_utf8() else {
+            return Route::NotFound;
+        };
+
+        let path = path.into_owned();
+        let target = target.to_owned();
+        let routing = self.clone();
+
+        tokio::task::spawn_blocking(move || {
+            routing.resolve(&path, |file| target.join(file).is_file())
+        })
+        .await
+        .unwrap_or(Route::NotFound)
+    }
+
+    /// The request for [`Self::not_found_page`]. Conditional and range headers are dropped, so
+    /// the page is always served in full.
+    pub(crate) fn not_found_request<B>(&self, request: &Request<B>) -> Option<Request<()>> {
+        let page = self.not_found_page.as_ref()?;
+
+        let mut not_found = Request::builder()
+            .method(request.method())
+            .uri(file_uri(page, None))
+            .version(request.version());
+
+        if let Some(accept_encoding) = request.headers().get(header::ACCEPT_ENCODING) {
+            not_found = not_found.header(header::ACCEPT_ENCODING, accept_encoding);
+        }
+
+        not_found.body(()).ok()
+    }
+}
+
+/// The URI of `file`, relative to `target`, keeping the query of the original request.
+pub(crate) fn file_uri(file: &str, query: Option<&str>) -> Uri {
+    let mut uri = String::new();
+
+    for segment in file.trim_start_matches('/').split('/') {
+        uri.push('/');
+        uri.extend(utf8_percent_encode(segment, PATH_SEGMENT));
+    }
+
+    if let Some(query) = query {
+        uri.push('?');
+        uri.push_str(query);
+    }
+
+    uri.parse().unwrap_or_else(|_| Uri::from_static("/"))
+}
+
+/// Redirect to `uri` with or without a trailing slash.
+pub(crate) fn redirect(uri: &Uri, trailing_slash: bool) -> Response<WebdevBody> {
+    let path = if trailing_slash {
+        format!("{}/", uri.path())
+    } else {
+        match uri.path().trim_end_matches('/') {
+            "" => "/".to_owned(),
+            path => path.to_owned(),
+        }
+    };
+
+    let location = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path,
+    };
+
+    Response::builder()
+        .status(StatusCode::TEMPORARY_REDIRECT)
+        .header(header::LOCATION, location)
+        .body(WebdevBody::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The output of an Astro build with `build.format: "file"` and a docs section in
+    /// directories.
+    const FILES: &[&str] = &[
+        "index.html",
+        "about.html",
+        "404.html",
+        "docs/index.html",
+        "docs/getting-started/index.html",
+        "_astro/index.BkA0k5xJ.js",
+        "favicon.svg",
+    ];
+
+    fn resolve(routing: &Routing, path: &str) -> Route {
+        routing.resolve(path, |file| FILES.contains(&file))
+    }
+
+    fn file(route: Route) -> Option<String> {
+        match route {
+            Route::File(file) => Some(file),
+            _ => None,
+        }
+    }
+
+    fn redirect(route: Route) -> Option<bool> {
+        match route {
+            Route::Redirect { trailing_slash } => Some(trailing_slash),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn serves_files() {
+        let routing = Routing::default();
+
+        assert_eq!(file(resolve(&routing, "/")).as_deref(), Some("index.html"));
+        assert_eq!(
+            file(resolve(&routing, "/_astro/index.BkA0k5xJ.js")).as_deref(),
+            Some("_astro/index.BkA0k5xJ.js")
+        );
+        assert_eq!(
+            file(resolve(&routing, "/docs/")).as_deref(),
+            Some("docs/index.html")
+        );
+        assert!(matches!(resolve(&routing, "/missing.js"), Route::NotFound));
+        assert!(matches!(
+            resolve(&routing, "/favicon.svg/"),
+            Route::NotFound
+        ));
+    }
+
+    #[test]
+    fn rejects_parent_segments() {
+        let routing = Routing::default();
+
+        assert!(matches!(
+            resolve(&routing, "/docs/../index.html"),
+            Route::NotFound
+        ));
+        assert!(matches!(
+            resolve(&routing, "/../about.html"),
+            Route::NotFound
+        ));
+    }
+
+    #[test]
+    fn resolves_clean_urls() {
+        let routing = Routing::default().clean_urls(true);
+
+        assert_eq!(redirect(resolve(&routing, "/about")), Some(true));
+        assert_eq!(
+            file(resolve(&routing, "/about/")).as_deref(),
+            Some("about.html")
+        );
+
+        // Without clean URLs, only directories have an index.
+        assert!(matches!(
+            resolve(&Routing::default(), "/about/"),
+            Route::NotFound
+        ));
+    }
+
+    #[test]
+    fn redirects_to_the_canonical_trailing_slash() {
+        let always = Routing::default().clean_urls(true);
+        let never = always.clone().trailing_slash(TrailingSlash::Never);
+        let ignore = always.clone().trailing_slash(TrailingSlash::Ignore);
+
+        assert_eq!(
+            redirect(resolve(&always, "/docs/getting-started")),
+            Some(true)
+        );
+        assert_eq!(
+            file(resolve(&always, "/docs/getting-started/")).as_deref(),
+            Some("docs/getting-started/index.html")
+        );
+
+        assert_eq!(redirect(resolve(&never, "/docs/")), Some(false));
+        assert_eq!(
+            file(resolve(&never, "/about")).as_deref(),
+            Some("about.html")
+        );
+        assert_eq!(
+            file(resolve(&never, "/docs")).as_deref(),
+            Some("docs/index.html")
+        );
+
+        assert_eq!(
+            file(resolve(&ignore, "/about")).as_deref(),
+            Some("about.html")
+        );
+        assert_eq!(
+            file(resolve(&ignore, "/docs/")).as_deref(),
+            Some("docs/index.html")
+        );
+    }
+
+    #[test]
+    fn builds_file_uris() {
+        assert_eq!(file_uri("about.html", None), "/about.html");
+        assert_eq!(
+            file_uri("docs/release notes.html", Some("v=2")),
+            "/docs/release%20notes.html?v=2"
+        );
+    }
+
+    #[test]
+    fn redirects_keep_the_query() {
+        let res = super::redirect(&"/about?ref=nav".parse().unwrap(), true);
+
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(res.headers()[header::LOCATION], "/about/?ref=nav");
+
+        let res = super::redirect(&"/docs/".parse().unwrap(), false);
+
+        assert_eq!(res.headers()[header::LOCATION], "/docs");
+    }
+}
@@ -0,0 +1,95 @@
+use http::{header, HeaderMap, Method, Request};
+use serde::{Deserialize, Serialize};
+
+use crate::routing;
+
+/// Serves a single file, usually `index.html`, for client-side routes of a single page app that
+/// do not exist in the production build.
+///
+/// Only navigation requests fall back, i.e. `GET` and `HEAD` requests that accept `text/html`
+/// and whose last path segment has no file extension. Missing assets like `/assets/app.js` are
+/// still answered with `404 Not Found`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpaFallback {
+    /// The file to serve, relative to `target`.
+    pub file: String,
+    /// Path prefixes that never fall back, e.g. `/api`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl Default for SpaFallback {
+    fn default() -> Self {
+        Self {
+            file: "index.html".into(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl SpaFallback {
+    pub fn file(mut self, value: impl Into<String>) -> Self {
+        self.file = value.into();
+
+        self
+    }
+
+    /// Never fall back for paths below `prefix`, e.g. `/api`.
+    pub fn exclude(mut self, prefix: impl Into<String>) -> Self {
+        self.exclude.push(prefix.into());
+
+        self
+    }
+
+    /// The request for the fallback file, if `request` is a navigation that may fall back.
+    pub(crate) fn request<B>(&self, request: &Request<B>) -> Option<Request<()>> {
+        if !self.applies_to(request) {
+            return None;
+        }
+
+        let mut fallback = Request::builder()
+            .method(request.method())
+            .uri(routing::file_uri(&self.file, None))
+            .version(request.version())
+            .body(())
+            .ok()?;
+
+        *fallback.headers_mut() = request.headers().clone();
+
+        Some(fallback)
+    }
+
+    fn applies_to<B>(&self, request: &Request<B>) -> bool {
+        if request.method() != Method::GET && request.method() != Method::HEAD {
+            return false;
+        }
+
+        let path = request.uri().path();
+        let last_segment = path.rsplit('/').next().unwrap_or_default();
+
+        !last_segment.contains('.')
+            && accepts_html(request.headers())
+            && !self.exclude.iter().any(|prefix| {
+                let prefix = prefix.trim_end_matches('/');
+
+                path == prefix
+                    || path
+                        .strip_prefix(prefix)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+    }
+}
+
+/// Whether `Accept` asks for an HTML document, as browsers do for navigations.
+pub(crate) fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_range| {
+            let media_type = media_range.split(';').next().unwrap_or_default().trim();
+
+            media_type.eq_ignore_ascii_case("text/html")
+        })
+}
@@ -0,0 +1,818 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{process::Child, sync::watch};
+
+use crate::{discovery::UrlMatcher, Config, WebdevError};
+
+/// Controls how the dev server is restarted after it exits unexpectedly.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RestartPolicy {
+    /// Maximum number of consecutive restarts before giving up. `None` restarts forever.
+    pub max_restarts: Option<u32>,
+    /// Delay before the first restart.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between restarts.
+    pub max_backoff: Duration,
+    /// Factor the delay is multiplied by after every restart.
+    pub multiplier: u32,
+    /// The restart counter is reset once the process has stayed up for this long.
+    pub reset_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: Some(5),
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2,
+            reset_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Never restart the dev server.
+    pub fn never() -> Self {
+        Self {
+            max_restarts: Some(0),
+            ..Default::default()
+        }
+    }
+
+    fn backoff(&self, restarts: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(self.multiplier.saturating_pow(restarts))
+            .min(self.max_backoff)
+    }
+}
+
+/// The lifecycle state of the supervised dev server process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DevServerState {
+    /// The process is being spawned.
+    Starting,
+    /// The process is running.
+    Running { pid: Option<u32> },
+    /// The process exited and will be restarted after `backoff`.
+    Restarting { attempt: u32, backoff: Duration },
+    /// The process exited successfully and will not be restarted.
+    Stopped,
+    /// The process kept failing and the restart limit was reached.
+    Failed,
+}
+
+pub(crate) struct Supervisor {
+    state: watch::Receiver<DevServerState>,
+    ready: watch::Receiver<bool>,
+    target: watch::Receiver<String>,
+    shutdown: watch::Sender<bool>,
+    process_group: Arc<Mutex<ProcessGroup>>,
+    grace_period: Duration,
+}
+
+/// The process group of the running dev server, shared between the supervising task and
+/// [`Supervisor`]'s `Drop`, so that it is only signalled while it exists and only once.
+#[derive(Debug, Default)]
+struct ProcessGroup {
+    pid: Option<u32>,
+    terminating: bool,
+}
+
+impl ProcessGroup {
+    fn started(&mut self, pid: Option<u32>) {
+        self.pid = pid;
+        self.terminating = false;
+    }
+
+    fn exited(&mut self) {
+        self.pid = None;
+    }
+
+    /// Send SIGTERM to the group, unless it was sent already.
+    fn terminate(&mut self) {
+        let Some(pid) = self.pid.filter(|_| !self.terminating) else {
+            return;
+        };
+
+        self.terminating = true;
+        signal_process_group(pid, Signal::Terminate);
+    }
+
+    /// Send SIGKILL to the group, but only while the dev server is unreaped. Until then it pins
+    /// its pid, so the group's id cannot have been reused.
+    fn kill(&self) {
+        if let Some(pid) = self.pid.filter(|pid| is_unreaped(*pid)) {
+            tracing::debug!(pid, "killing dev server process group");
+            signal_process_group(pid, Signal::Kill);
+        }
+    }
+}
+
+impl Supervisor {
+    pub(crate) fn spawn(config: Config, url_matcher: Option<UrlMatcher>) -> Self {
+        let (state_tx, state) = watch::channel(DevServerState::Starting);
+        let (ready_tx, ready) = watch::channel(false);
+        let (target_tx, target) = watch::channel(config.dev_server_url());
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let process_group = Arc::new(Mutex::new(ProcessGroup::default()));
+        let grace_period = config.shutdown_grace_period;
+
+        tokio::spawn(supervise(
+            config,
+            state_tx,
+            ready_tx,
+            target_tx,
+            url_matcher,
+            shutdown_rx,
+            process_group.clone(),
+        ));
+
+        Self {
+            state,
+            ready,
+            target,
+            shutdown,
+            process_group,
+            grace_period,
+        }
+    }
+
+    pub(crate) fn state(&self) -> watch::Receiver<DevServerState> {
+        self.state.clone()
+    }
+
+    /// The URL the dev server is currently listening on.
+    pub(crate) fn target(&self) -> String {
+        self.target.borrow().clone()
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Resolve once the dev server accepts requests, or fail if it stopped running.
+    pub(crate) fn ready(&self) -> impl Future<Output = Result<(), WebdevError>> + Send + 'static {
+        let mut ready = self.ready.clone();
+        let mut state = self.state.clone();
+
+        async move {
+            tokio::select! {
+                result = ready.wait_for(|ready| *ready) => {
+                    result.map(|_| ()).map_err(|_| WebdevError::DevServerStopped)
+                }
+                _ = state.wait_for(|state| {
+                    matches!(state, DevServerState::Stopped | DevServerState::Failed)
+                }) => Err(WebdevError::DevServerStopped),
+            }
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.shutdown.send_replace(true);
+
+        // The supervising task terminates the dev server gracefully, but it will not get the
+        // chance to if the runtime is shutting down as well, so signal the process group directly.
+        let running = {
+            let mut process_group = self.process_group.lock().unwrap();
+            process_group.terminate();
+
+            process_group.pid.is_some()
+        };
+
+        if !running {
+            return;
+        }
+
+        let process_group = self.process_group.clone();
+        let grace_period = self.grace_period;
+
+        std::thread::spawn(move || {
+            std::thread::sleep(grace_period);
+            process_group.lock().unwrap().kill();
+        });
+    }
+}
+
+/// Stops the dev server of a [`WebdevService`](crate::WebdevService).
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    inner: Option<(watch::Sender<bool>, watch::Receiver<DevServerState>)>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(supervisor: Option<&Supervisor>) -> Self {
+        Self {
+            inner: supervisor
+                .map(|supervisor| (supervisor.shutdown.clone(), supervisor.state.clone())),
+        }
+    }
+
+    /// Send SIGTERM to the dev server's process group, then SIGKILL once the dev server exited
+    /// or the configured grace period has passed. Resolves after the dev server exited.
+    pub async fn shutdown(&self) {
+        let Some((shutdown, state)) = &self.inner else {
+            return;
+        };
+
+        shutdown.send_replace(true);
+
+        let _ = state
+            .clone()
+            .wait_for(|state| matches!(state, DevServerState::Stopped | DevServerState::Failed))
+            .await;
+    }
+}
+
+async fn supervise(
+    config: Config,
+    state: watch::Sender<DevServerState>,
+    ready: watch::Sender<bool>,
+    target: watch::Sender<String>,
+    url_matcher: Option<UrlMatcher>,
+    mut shutdown: watch::Receiver<bool>,
+    process_group: Arc<Mutex<ProcessGroup>>,
+) {
+    let policy = config.restart_policy.clone();
+    let mut restarts = 0;
+
+    loop {
+        if *shutdown.borrow_and_update() {
+            state.send_replace(DevServerState::Stopped);
+
+            return;
+        }
+
+        tracing::info!("starting dev server");
+        state.send_replace(DevServerState::Starting);
+
+        let started = Instant::now();
+
+        let on_line = {
+            let target = target.clone();
+            let url_matcher = url_matcher.clone();
+
+            move |line: &str| {
+                let Some(url) = url_matcher.as_ref().and_then(|m| m.find(line)) else {
+                    return;
+                };
+
+                target.send_if_modified(|target| {
+                    if *target == url {
+                        return false;
+                    }
+
+                    tracing::info!("dev server is listening on {}", url);
+                    *target = url;
+
+                    true
+                });
+            }
+        };
+
+        let result = match config.spawn_dev(on_line) {
+            Ok(mut dev_process) => {
+                let pid = dev_process.id();
+
+                tracing::info!(?pid, "dev server running");
+                state.send_replace(DevServerState::Running { pid });
+                process_group.lock().unwrap().started(pid);
+
+                let watchdog = spawn_watchdog(pid, config.shutdown_grace_period);
+
+                let mut current_target = target.subscribe();
+                let probe = config.readiness.probe(
+                    current_target.borrow_and_update().clone(),
+                    config.dev_server_connection(),
+                );
+                tokio::pin!(probe);
+
+                let mut probing = true;
+
+                let result = loop {
+                    tokio::select! {
+                        result = dev_process.wait() => break Some(result),
+                        _ = shutdown.wait_for(|shutdown| *shutdown) => break None,
+                        _ = &mut probe, if probing => {
+                            tracing::info!("dev server is ready at {}", *current_target.borrow());
+                            ready.send_replace(true);
+                            probing = false;
+                        }
+                        Ok(()) = current_target.changed() => {
+                            ready.send_replace(false);
+                            probe.set(
+                                config.readiness.probe(
+                                    current_target.borrow_and_update().clone(),
+                                    config.dev_server_connection(),
+                                ),
+                            );
+                            probing = true;
+                        }
+                    }
+                };
+
+                ready.send_replace(false);
+
+                let result = match result {
+                    Some(result) => result.map_err(WebdevError::from),
+                    None => {
+                        terminate(
+                            &mut dev_process,
+                            &process_group,
+                            config.shutdown_grace_period,
+                        )
+                        .await;
+                        process_group.lock().unwrap().exited();
+                        stop_watchdog(watchdog).await;
+                        state.send_replace(DevServerState::Stopped);
+
+                        return;
+                    }
+                };
+
+                process_group.lock().unwrap().exited();
+                stop_watchdog(watchdog).await;
+
+                result
+            }
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(status) if status.success() => {
+                tracing::info!("dev server exited");
+                state.send_replace(DevServerState::Stopped);
+
+                return;
+            }
+            Ok(status) => {
+                tracing::warn!("dev server exited with {}", status);
+            }
+            Err(error) => {
+                tracing::error!("error running dev server: {}", error);
+            }
+        }
+
+        if started.elapsed() >= policy.reset_after {
+            restarts = 0;
+        }
+
+        if policy.max_restarts.is_some_and(|max| restarts >= max) {
+            tracing::error!("dev server failed after {} restarts, giving up", restarts);
+            state.send_replace(DevServerState::Failed);
+
+            return;
+        }
+
+        let backoff = policy.backoff(restarts);
+        restarts += 1;
+
+        tracing::warn!(attempt = restarts, "restarting dev server in {:?}", backoff);
+        state.send_replace(DevServerState::Restarting {
+            attempt: restarts,
+            backoff,
+        });
+
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.wait_for(|shutdown| *shutdown) => {}
+        }
+    }
+}
+
+/// Spawn a process into the dev server's process group that sends it SIGTERM, and SIGKILL after
+/// `grace_period`, once this process exits. Outside of the terminal's foreground group the dev
+/// server does not get Ctrl-C along with this process, which may exit on it without dropping the
+/// service.
+#[cfg(unix)]
+fn spawn_watchdog(pid: Option<u32>, grace_period: Duration) -> Option<Child> {
+    let pid = pid?;
+
+    // `read` returns once the pipe to its stdin, only held by this process, is closed. The
+    // watchdog is part of the group, so its id stays reserved for as long as it runs.
+    let script = format!(
+        "read _; trap '' TERM; kill -TERM 0; sleep {:.3}; kill -KILL 0",
+        grace_period.as_secs_f64()
+    );
+
+    tokio::process::Command::new("sh")
+        .args(["-c", &script])
+        .process_group(pid as i32)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .inspect_err(|error| tracing::warn!("failed to spawn dev server watchdog: {}", error))
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn spawn_watchdog(_pid: Option<u32>, _grace_period: Duration) -> Option<Child> {
+    None
+}
+
+/// Kill the watchdog without it signalling the dev server's process group.
+async fn stop_watchdog(watchdog: Option<Child>) {
+    if let Some(mut watchdog) = watchdog {
+        if let Err(error) = watchdog.kill().await {
+            tracing::error!("error killing dev server watchdog: {}", error);
+        }
+    }
+}
+
+/// How often [`terminate`] checks whether the dev server exited.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Ask the dev server to exit, killing it if it is still running after `grace_period`. Whatever
+/// is left of its process group once it exited is killed as well.
+async fn terminate(
+    dev_process: &mut Child,
+    process_group: &Mutex<ProcessGroup>,
+    grace_period: Duration,
+) {
+    let Some(pid) = dev_process.id() else {
+        return;
+    };
+
+    tracing::info!(pid, "stopping dev server");
+    process_group.lock().unwrap().terminate();
+
+    // The dev server is only reaped once its group was killed, see `ProcessGroup::kill`.
+    if tokio::time::timeout(grace_period, wait_exited(dev_process))
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "dev server did not exit within {:?}, killing it",
+            grace_period
+        );
+    }
+
+    process_group.lock().unwrap().kill();
+
+    #[cfg(not(unix))]
+    if let Err(error) = dev_process.start_kill() {
+        tracing::error!("error killing dev server: {}", error);
+    }
+
+    wait_exited(dev_process).await;
+
+    // Reap while holding the lock, so that `Supervisor`'s `Drop` cannot signal the group after.
+    let _process_group = process_group.lock().unwrap();
+
+    match dev_process.try_wait() {
+        Ok(Some(status)) => {
+            tracing::info!("dev server exited with {}", status);
+        }
+        Ok(None) => {}
+        Err(error) => {
+            tracing::error!("error waiting for dev server: {}", error);
+        }
+    }
+}
+
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+#[cfg(unix)]
+fn signal_process_group(pid: u32, signal: Signal) {
+    let signal = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+
+    // SAFETY: `kill` has no memory safety requirements. The dev server was spawned as the leader
+    // of its own process group, so a negative pid addresses the whole group.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), signal);
+    }
+}
+
+async fn wait_exited(dev_process: &mut Child) {
+    while !has_exited(dev_process) {
+        tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+    }
+}
+
+/// The exit of the child `pid`, if it exited, without reaping it. `None` if it is not an
+/// unreaped child of this process.
+#[cfg(unix)]
+fn peek_exit(pid: u32) -> Option<bool> {
+    let mut info = std::mem::MaybeUninit::<libc::siginfo_t>::zeroed();
+
+    // SAFETY: `info` is valid for writes. `WNOWAIT` leaves the child waitable.
+    let result = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            pid as libc::id_t,
+            info.as_mut_ptr(),
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+
+    // SAFETY: `waitid` zeroes `info` or fills it in on success, and it was zeroed before.
+    (result == 0).then(|| unsafe { info.assume_init() }.si_signo == libc::SIGCHLD)
+}
+
+/// Whether `pid` is a child of this process that was not reaped yet.
+#[cfg(unix)]
+fn is_unreaped(pid: u32) -> bool {
+    peek_exit(pid).is_some()
+}
+
+#[cfg(not(unix))]
+fn is_unreaped(_pid: u32) -> bool {
+    false
+}
+
+/// Whether the dev server exited. On unix it is left unreaped.
+#[cfg(unix)]
+fn has_exited(dev_process: &mut Child) -> bool {
+    dev_process
+        .id()
+        .is_none_or(|pid| peek_exit(pid).unwrap_or(true))
+}
+
+#[cfg(not(unix))]
+fn has_exited(dev_process: &mut Child) -> bool {
+    !matches!(dev_process.try_wait(), Ok(None))
+}
+
+#[cfg(not(unix))]
+fn signal_process_group(_pid: u32, _signal: Signal) {
+    // Process groups are unix only. The child is spawned with `kill_on_drop` so it is still
+    // killed once the supervising task drops it.
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(target_os = "linux")]
+    use std::path::Path;
+
+    use crate::Mode;
+
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    /// Ignores SIGTERM, like a dev server stuck in a shutdown hook, and leaves a child behind
+    /// that does as well. Both pids are written to `pids`.
+    const STUBBORN_DEV_SERVER: &str = r#"
+const { spawn } = require('child_process');
+process.on('SIGTERM', () => {});
+const child = spawn(process.execPath, ['-e', "process.on('SIGTERM', () => {}); setInterval(() => {}, 1000)"], { stdio: 'ignore' });
+require('fs').writeFileSync('pids', `${process.pid} ${child.pid}`);
+setInterval(() => {}, 1000);
+"#;
+
+    fn has_npm() -> bool {
+        let found = std::process::Command::new("npm")
+            .arg("--version")
+            .output()
+            .is_ok();
+
+        if !found {
+            eprintln!("skipping, npm is not installed");
+        }
+
+        found
+    }
+
+    fn project(dev_server: &str) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("package.json"),
+            r#"{ "name": "supervisor", "private": true, "scripts": { "dev": "node server.js" } }"#,
+        )
+        .unwrap();
+        std::fs::write(root.path().join("server.js"), dev_server).unwrap();
+
+        root
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn dev_server_pids(root: &Path) -> Vec<u32> {
+        loop {
+            if let Ok(pids) = std::fs::read_to_string(root.join("pids")) {
+                return pids.split(' ').map(|pid| pid.parse().unwrap()).collect();
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    /// Whether `pid` is running, zombies are dead.
+    fn is_running(pid: u32) -> bool {
+        std::fs::read_to_string(format!("/proc/{pid}/stat"))
+            .is_ok_and(|stat| !stat.rsplit_once(") ").unwrap().1.starts_with('Z'))
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn assert_stopped(pids: &[u32], within: Duration) {
+        let deadline = Instant::now() + within;
+
+        while pids.iter().any(|pid| is_running(*pid)) {
+            assert!(Instant::now() < deadline, "{pids:?} are still running");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Record the states the supervisor passes through until `done` returns true. `Starting`
+    /// is replaced by `Running` without yielding in between, so only the first one is seen.
+    async fn states_until(
+        supervisor: &Supervisor,
+        done: impl Fn(&[DevServerState]) -> bool,
+    ) -> Vec<DevServerState> {
+        let mut receiver = supervisor.state();
+        let mut states = vec![receiver.borrow_and_update().clone()];
+
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while !done(&states) {
+                receiver.changed().await.unwrap();
+                states.push(receiver.borrow_and_update().clone());
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("timed out after {states:?}"));
+
+        states
+    }
+
+    #[test]
+    fn backoff_saturates_at_max_backoff() {
+        let policy = RestartPolicy::default();
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(5), Duration::from_secs(16));
+        assert_eq!(policy.backoff(6), Duration::from_secs(30));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(30));
+
+        let policy = RestartPolicy {
+            initial_backoff: Duration::MAX,
+            max_backoff: Duration::MAX,
+            multiplier: u32::MAX,
+            ..RestartPolicy::default()
+        };
+
+        assert_eq!(policy.backoff(2), Duration::MAX);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_restarts() {
+        if !has_npm() {
+            return;
+        }
+
+        let root = project("process.exit(1);");
+        let config =
+            Config::new_npm(Mode::Development, root.path()).restart_policy(RestartPolicy {
+                max_restarts: Some(2),
+                initial_backoff: Duration::from_millis(10),
+                ..RestartPolicy::default()
+            });
+        let supervisor = Supervisor::spawn(config, None);
+
+        let states = states_until(&supervisor, |states| {
+            states.last() == Some(&DevServerState::Failed)
+        })
+        .await;
+        let states: Vec<_> = states
+            .iter()
+            .map(|state| match state {
+                DevServerState::Running { .. } => "running".to_owned(),
+                DevServerState::Restarting { attempt, backoff } => {
+                    format!("restarting {attempt} in {backoff:?}")
+                }
+                state => format!("{state:?}").to_lowercase(),
+            })
+            .collect();
+
+        assert_eq!(
+            states,
+            [
+                "starting",
+                "running",
+                "restarting 1 in 10ms",
+                "running",
+                "restarting 2 in 20ms",
+                "running",
+                "failed",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn restarts_are_reset_once_the_dev_server_stayed_up() {
+        if !has_npm() {
+            return;
+        }
+
+        let root = project("setTimeout(() => process.exit(1), 200);");
+        let config =
+            Config::new_npm(Mode::Development, root.path()).restart_policy(RestartPolicy {
+                max_restarts: Some(1),
+                initial_backoff: Duration::from_millis(10),
+                reset_after: Duration::from_millis(100),
+                ..RestartPolicy::default()
+            });
+        let supervisor = Supervisor::spawn(config, None);
+
+        let states = states_until(&supervisor, |states| {
+            let restarts = states
+                .iter()
+                .filter(|state| matches!(state, DevServerState::Restarting { .. }))
+                .count();
+
+            restarts == 3 || states.last() == Some(&DevServerState::Failed)
+        })
+        .await;
+
+        ShutdownHandle::new(Some(&supervisor)).shutdown().await;
+
+        assert!(states.iter().all(|state| match state {
+            DevServerState::Restarting { attempt, backoff } => {
+                *attempt == 1 && *backoff == Duration::from_millis(10)
+            }
+            state => *state != DevServerState::Failed,
+        }));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn shutdown_kills_processes_ignoring_sigterm() {
+        if !has_npm() {
+            return;
+        }
+
+        let root = project(STUBBORN_DEV_SERVER);
+        let config = Config::new_npm(Mode::Development, root.path())
+            .shutdown_grace_period(Duration::from_millis(300));
+        let supervisor = Supervisor::spawn(config, None);
+        let pids = dev_server_pids(root.path()).await;
+
+        ShutdownHandle::new(Some(&supervisor)).shutdown().await;
+
+        assert_eq!(*supervisor.state().borrow(), DevServerState::Stopped);
+        assert_stopped(&pids, Duration::from_secs(2)).await;
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn dropping_the_supervisor_kills_the_process_group() {
+        if !has_npm() {
+            return;
+        }
+
+        let root = project(STUBBORN_DEV_SERVER);
+        let config = Config::new_npm(Mode::Development, root.path())
+            .shutdown_grace_period(Duration::from_millis(300));
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (supervisor, pids) = runtime.block_on(async {
+            let supervisor = Supervisor::spawn(config, None);
+            let pids = dev_server_pids(root.path()).await;
+
+            (supervisor, pids)
+        });
+
+        // As at the end of `main`, the runtime goes away along with the supervising task.
+        drop(supervisor);
+        drop(runtime);
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(assert_stopped(&pids, Duration::from_secs(2)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn zombies_are_unreaped() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+
+        while peek_exit(child.id()) == Some(false) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(peek_exit(child.id()), Some(true));
+        assert!(is_unreaped(child.id()));
+
+        child.wait().unwrap();
+
+        assert!(!is_unreaped(child.id()));
+    }
+}
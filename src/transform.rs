@@ -0,0 +1,551 @@
+//! Rewriting HTML responses while they are streamed to the client.
+
+use std::{
+    borrow::Cow,
+    pin::Pin,
+    sync::{Arc, LazyLock, Mutex},
+    task::{ready, Context, Poll},
+};
+
+use bytes::Bytes;
+use http::{header, request::Parts, HeaderMap, Response, StatusCode};
+use http_body::{Body as HttpBody, Frame};
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt};
+use lol_html::{
+    html_content::{ContentType, TextChunk},
+    send::{DocumentContentHandlers, Element, ElementContentHandlers, HtmlRewriter, Settings},
+    HandlerResult, OutputSink, Selector,
+};
+use regex::{Captures, Regex};
+
+use crate::WebdevBody;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+type ElementHandler = Box<dyn FnMut(&mut Element<'_, '_>) -> HandlerResult + Send>;
+
+/// Rewrites the HTML responses of a [`WebdevService`](crate::WebdevService), both from the dev
+/// server and from the production build.
+///
+/// Implemented for closures taking the request and the [`HtmlRewrite`] to configure, so values
+/// like a CSRF token can differ per request.
+pub trait HtmlTransform: Send + Sync + 'static {
+    fn transform(&self, request: &Parts, html: &mut HtmlRewrite);
+}
+
+impl<F> HtmlTransform for F
+where
+    F: Fn(&Parts, &mut HtmlRewrite) + Send + Sync + 'static,
+{
+    fn transform(&self, request: &Parts, html: &mut HtmlRewrite) {
+        self(request, html)
+    }
+}
+
+/// The rewrites applied to a single HTML response.
+#[derive(Default)]
+pub struct HtmlRewrite {
+    elements: Vec<(String, ElementHandler)>,
+    /// Template variables, with the markup they are replaced with.
+    variables: Vec<(String, String)>,
+}
+
+impl HtmlRewrite {
+    /// Call `handler` for each element matching the CSS `selector`. Invalid selectors are
+    /// logged and ignored.
+    pub fn element<F>(&mut self, selector: impl Into<String>, handler: F) -> &mut Self
+    where
+        F: FnMut(&mut Element<'_, '_>) -> HandlerResult + Send + 'static,
+    {
+        self.elements.push((selector.into(), Box::new(handler)));
+
+        self
+    }
+
+    /// Insert `html` at the start of `<head>`, e.g. a `<base href>`.
+    pub fn prepend_to_head(&mut self, html: impl Into<String>) -> &mut Self {
+        let html = html.into();
+
+        self.element("head", move |element| {
+            element.prepend(&html, ContentType::Html);
+
+            Ok(())
+        })
+    }
+
+    /// Insert `html` at the end of `<head>`.
+    pub fn append_to_head(&mut self, html: impl Into<String>) -> &mut Self {
+        let html = html.into();
+
+        self.element("head", move |element| {
+            element.append(&html, ContentType::Html);
+
+            Ok(())
+        })
+    }
+
+    /// Insert `html` at the end of `<body>`.
+    pub fn append_to_body(&mut self, html: impl Into<String>) -> &mut Self {
+        let html = html.into();
+
+        self.element("body", move |element| {
+            element.append(&html, ContentType::Html);
+
+            Ok(())
+        })
+    }
+
+    /// Replace `{{ name }}` in text and attribute values with `value`, escaped as HTML.
+    ///
+    /// The contents of `<script>` and `<style>` are not HTML, use [`Self::raw_variable`] there.
+    pub fn variable(&mut self, name: impl Into<String>, value: &str) -> &mut Self {
+        self.raw_variable(name, escape(value))
+    }
+
+    /// Replace `{{ name }}` with `value` as is, e.g. JSON inside of a `<script>`.
+    pub fn raw_variable(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.variables.push((name.into(), value.into()));
+
+        self
+    }
+
+    fn into_settings(self) -> Settings<'static, 'static> {
+        let mut element_content_handlers = Vec::new();
+
+        for (selector, handler) in self.elements {
+            match selector.parse::<Selector>() {
+                Ok(selector) => element_content_handlers.push((
+                    Cow::Owned(selector),
+                    ElementContentHandlers::default().element(handler),
+                )),
+                Err(error) => {
+                    tracing::error!(
+                        "invalid selector {:?} in html transform: {}",
+                        selector,
+                        error
+                    );
+                }
+            }
+        }
+
+        let mut document_content_handlers = Vec::new();
+
+        if !self.variables.is_empty() {
+            let variables = Arc::new(Variables(self.variables));
+
+            let attributes = variables.clone();
+
+            element_content_handlers.push((
+                Cow::Owned("*".parse().expect("universal selector is valid")),
+                ElementContentHandlers::default().element(move |element: &mut Element| {
+                    let replaced = element
+                        .attributes()
+                        .iter()
+                        .filter_map(|attribute| {
+                            let value = attribute.value();
+                            let replaced = attributes.replace(&value);
+
+                            (replaced != value).then(|| (attribute.name(), replaced.into_owned()))
+                        })
+                        .collect::<Vec<_>>();
+
+                    for (name, value) in replaced {
+                        element.set_attribute(&name, &value)?;
+                    }
+
+                    Ok(())
+                }),
+            ));
+
+            // A variable can be split across the chunks of a text node, so the whole node is
+            // buffered and written out with its last chunk.
+            let mut text = String::new();
+
+            document_content_handlers.push(DocumentContentHandlers::default().text(
+                move |chunk: &mut TextChunk| {
+                    text.push_str(chunk.as_str());
+
+                    if chunk.last_in_text_node() {
+                        chunk.replace(&variables.replace(&text), ContentType::Html);
+                        text.clear();
+                    } else {
+                        chunk.remove();
+                    }
+
+                    Ok(())
+                },
+            ));
+        }
+
+        Settings {
+            element_content_handlers,
+            document_content_handlers,
+            ..Settings::new_send()
+        }
+    }
+}
+
+/// A template variable like `{{ csrf_token }}`.
+static VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([\w.-]+)\s*\}\}").unwrap());
+
+struct Variables(Vec<(String, String)>);
+
+impl Variables {
+    fn replace<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !text.contains("{{") {
+            return Cow::Borrowed(text);
+        }
+
+        VARIABLE.replace_all(text, |captures: &Captures| {
+            self.0
+                .iter()
+                .find(|(name, _)| *name == captures[1])
+                .map_or_else(|| captures[0].to_owned(), |(_, value)| value.clone())
+        })
+    }
+}
+
+/// Rewrite `res` with the rewrites set up by `configure` if it is an HTML document that can be
+/// rewritten.
+pub(crate) fn apply(
+    request: &Parts,
+    res: Response<WebdevBody>,
+    configure: impl FnOnce(&mut HtmlRewrite),
+) -> Response<WebdevBody> {
+    let is_html = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+
+    let is_encoded = res
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|value| value != "identity");
+
+    if !is_html
+        || matches!(
+            res.status(),
+            StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+        )
+        || request.method == http::Method::HEAD
+    {
+        return res;
+    }
+
+    if is_encoded {
+        tracing::warn!(
+            "not transforming compressed html response to {}",
+            request.uri
+        );
+
+        return res;
+    }
+
+    let mut html = HtmlRewrite::default();
+    configure(&mut html);
+
+    let (mut parts, body) = res.into_parts();
+
+    // The rewritten document differs in length, and can differ per request.
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::ACCEPT_RANGES);
+    parts.headers.remove(header::ETAG);
+    parts.headers.remove(header::LAST_MODIFIED);
+
+    Response::from_parts(parts, WebdevBody::Html(HtmlBody::new(html, body)))
+}
+
+/// Collects the output of the rewriter until it is polled.
+struct Sink(Arc<Mutex<Vec<u8>>>);
+
+impl OutputSink for Sink {
+    fn handle_chunk(&mut self, chunk: &[u8]) {
+        self.0.lock().unwrap().extend_from_slice(chunk);
+    }
+}
+
+/// A body that is rewritten by an [`HtmlTransform`].
+pub struct HtmlBody {
+    inner: UnsyncBoxBody<Bytes, BoxError>,
+    /// `None` once the inner body ended.
+    rewriter: Option<Box<HtmlRewriter<'static, Sink>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    /// Trailers of the inner body, sent after the rest of the document.
+    trailers: Option<HeaderMap>,
+}
+
+impl HtmlBody {
+    fn new<B>(html: HtmlRewrite, inner: B) -> Self
+    where
+        B: HttpBody<Data = Bytes, Error = BoxError> + Send + 'static,
+    {
+        let output = Arc::new(Mutex::new(Vec::new()));
+
+        Self {
+            inner: inner.boxed_unsync(),
+            rewriter: Some(Box::new(HtmlRewriter::new(
+                html.into_settings(),
+                Sink(output.clone()),
+            ))),
+            output,
+            trailers: None,
+        }
+    }
+}
+
+impl HttpBody for HtmlBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+
+        loop {
+            let output = std::mem::take(&mut *this.output.lock().unwrap());
+
+            if !output.is_empty() {
+                return Poll::Ready(Some(Ok(Frame::data(Bytes::from(output)))));
+            }
+
+            let Some(rewriter) = this.rewriter.as_mut() else {
+                return Poll::Ready(
+                    this.trailers
+                        .take()
+                        .map(|trailers| Ok(Frame::trailers(trailers))),
+                );
+            };
+
+            match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => rewriter.write(&data)?,
+                    // Trailers end the body, so the rest of the document is sent before them.
+                    Err(frame) => {
+                        this.trailers = frame.into_trailers().ok();
+                        this.rewriter.take().expect("rewriter is set").end()?;
+                    }
+                },
+                Some(Err(error)) => return Poll::Ready(Some(Err(error))),
+                None => this.rewriter.take().expect("rewriter is set").end()?,
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.rewriter.is_none() && self.trailers.is_none() && self.output.lock().unwrap().is_empty()
+    }
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+    use http::{HeaderValue, Method, Request};
+    use http_body_util::StreamBody;
+
+    use super::*;
+
+    fn request(method: Method) -> Parts {
+        Request::builder()
+            .method(method)
+            .uri("/")
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0
+    }
+
+    /// A body sending `chunks`, then `trailers`.
+    fn chunked(
+        chunks: &[&'static str],
+        trailers: Option<HeaderMap>,
+    ) -> impl HttpBody<Data = Bytes, Error = BoxError> + Send + 'static {
+        let frames = chunks
+            .iter()
+            .map(|chunk| Ok(Frame::data(Bytes::from_static(chunk.as_bytes()))))
+            .chain(trailers.map(|trailers| Ok(Frame::trailers(trailers))))
+            .collect::<Vec<Result<_, BoxError>>>();
+
+        StreamBody::new(stream::iter(frames))
+    }
+
+    async fn rewrite(chunks: &[&'static str], configure: impl FnOnce(&mut HtmlRewrite)) -> String {
+        let mut html = HtmlRewrite::default();
+        configure(&mut html);
+
+        let body = HtmlBody::new(html, chunked(chunks, None));
+        let bytes = body.collect().await.unwrap().to_bytes();
+
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    fn html_response(body: &'static str) -> Response<WebdevBody> {
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(WebdevBody::full(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn replaces_variables_split_across_chunks() {
+        let html = rewrite(
+            &[
+                "<p>Hello {{ us",
+                "er }}, your token is {",
+                "{csrf_token}}</p>",
+            ],
+            |html| {
+                html.variable("user", "Ada").variable("csrf_token", "a1b2");
+            },
+        )
+        .await;
+
+        assert_eq!(html, "<p>Hello Ada, your token is a1b2</p>");
+    }
+
+    #[tokio::test]
+    async fn replaces_variables_in_attributes() {
+        let html = rewrite(
+            &[r#"<meta name="csrf-token" content="{{ csrf_token }}"><a href="/u/{{user}}" class="x">{{ missing }}</a>"#],
+            |html| {
+                html.variable("csrf_token", "a1b2").variable("user", "ada");
+            },
+        )
+        .await;
+
+        assert_eq!(
+            html,
+            r#"<meta name="csrf-token" content="a1b2"><a href="/u/ada" class="x">{{ missing }}</a>"#
+        );
+    }
+
+    #[tokio::test]
+    async fn escapes_variables_but_not_raw_variables() {
+        let html = rewrite(
+            &[r#"<p title="{{ name }}">{{ name }}</p><script>window.state = {{ state }};</script>"#],
+            |html| {
+                html.variable("name", r#"<b>"Tom" & Jerry</b>"#)
+                    .raw_variable("state", r#"{"user":"ada"}"#);
+            },
+        )
+        .await;
+
+        assert_eq!(
+            html,
+            "<p title=\"&lt;b&gt;&quot;Tom&quot; &amp; Jerry&lt;/b&gt;\">\
+             &lt;b&gt;&quot;Tom&quot; &amp; Jerry&lt;/b&gt;</p>\
+             <script>window.state = {\"user\":\"ada\"};</script>"
+        );
+    }
+
+    #[tokio::test]
+    async fn inserts_into_head_and_body() {
+        let html = rewrite(
+            &["<html><head><title>App</title></head><body><div id=\"app\"></div></body></html>"],
+            |html| {
+                html.prepend_to_head(r#"<base href="/app/">"#)
+                    .append_to_head(r#"<meta name="x" content="y">"#)
+                    .append_to_body("<script>1</script>")
+                    .element("[invalid", |_| Ok(()));
+            },
+        )
+        .await;
+
+        assert_eq!(
+            html,
+            "<html><head><base href=\"/app/\"><title>App</title><meta name=\"x\" content=\"y\">\
+             </head><body><div id=\"app\"></div><script>1</script></body></html>"
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_trailers_after_the_document() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("server-timing", HeaderValue::from_static("render;dur=12"));
+
+        let mut html = HtmlRewrite::default();
+        html.variable("user", "ada");
+
+        // The last text node is only complete once the body ended.
+        let mut body = HtmlBody::new(html, chunked(&["<p>Hello ", "{{ user }}"], Some(trailers)));
+
+        let mut data = String::new();
+        let mut received_trailers = None;
+
+        while let Some(frame) = body.frame().await {
+            let frame = frame.unwrap();
+
+            assert!(received_trailers.is_none(), "frame after the trailers");
+
+            match frame.into_data() {
+                Ok(chunk) => data.push_str(std::str::from_utf8(&chunk).unwrap()),
+                Err(frame) => received_trailers = frame.into_trailers().ok(),
+            }
+        }
+
+        assert_eq!(data, "<p>Hello ada");
+        assert_eq!(received_trailers.unwrap()["server-timing"], "render;dur=12");
+        assert!(body.is_end_stream());
+    }
+
+    #[tokio::test]
+    async fn rewrites_html_responses() {
+        let res = apply(
+            &request(Method::GET),
+            html_response("<p>{{ user }}</p>"),
+            |html| {
+                html.variable("user", "ada");
+            },
+        );
+
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "<p>ada</p>");
+    }
+
+    #[tokio::test]
+    async fn skips_responses_that_cannot_be_rewritten() {
+        let configure = |html: &mut HtmlRewrite| {
+            html.variable("user", "ada");
+        };
+
+        let mut encoded = html_response("<p>{{ user }}</p>");
+        encoded
+            .headers_mut()
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static("br"));
+
+        let mut not_modified = html_response("");
+        *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+
+        let mut script = html_response("const user = '{{ user }}'");
+        script.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/javascript"),
+        );
+
+        for (method, res) in [
+            (Method::GET, encoded),
+            (Method::GET, not_modified),
+            (Method::GET, script),
+            (Method::HEAD, html_response("<p>{{ user }}</p>")),
+        ] {
+            let res = apply(&request(method), res, configure);
+
+            assert!(res.headers().contains_key(header::CONTENT_LENGTH));
+            assert!(!matches!(res.body(), WebdevBody::Html(_)));
+        }
+    }
+}
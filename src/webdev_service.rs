@@ -1,56 +1,327 @@
 use std::{
-    path::PathBuf,
+    collections::VecDeque,
+    net::IpAddr,
+    path::{Path, PathBuf},
     process::Stdio,
-    task::{Context, Poll},
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+    time::Duration,
 };
 
 use futures_util::future::BoxFuture;
-use http::{Request, Response};
+use globset::{Glob, GlobSetBuilder};
+use http::{header, HeaderValue, Method, Request, Response, StatusCode, Uri};
 use http_body::Body as HttpBody;
-use http_body_util::Either;
+#[cfg(unix)]
+use insecure_reverse_proxy::UnixReverseProxyService;
 use insecure_reverse_proxy::{
-    HttpReverseProxyService, InsecureReverseProxyService, InsecureReverseProxyServiceBody,
+    ClientIpExtractor, ForwardedHeaders, HttpReverseProxyService, InsecureReverseProxyService,
+    ProxyError,
 };
+#[cfg(feature = "https")]
+use insecure_reverse_proxy::{HttpsOptions, HttpsReverseProxyService};
+use percent_encoding::percent_decode_str;
 use serde::{Deserialize, Serialize};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::{ChildStdout, Command},
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    process::{Child, Command},
+    sync::watch,
+    task::JoinHandle,
 };
 use tower::Service;
-use tower_http::services::{fs::ServeFileSystemResponseBody, ServeDir};
+use tower_http::services::ServeDir;
+use walkdir::WalkDir;
+
+use crate::{
+    base_path,
+    body::WebdevBody,
+    caching::{CachePolicy, CacheRules},
+    csp::{self, ContentSecurityPolicy, CspNonce},
+    discovery::UrlMatcher,
+    fingerprint::{Fingerprint, Stamp},
+    integrity::{self, Integrity},
+    package_manager::PackageManager,
+    readiness::{Connection, Readiness},
+    routing::{self, Route, Routing},
+    spa::{self, SpaFallback},
+    supervisor::{DevServerState, RestartPolicy, ShutdownHandle, Supervisor},
+    transform::{self, HtmlTransform},
+    WebdevError,
+};
+#[cfg(feature = "embed")]
+use crate::{embed, EmbeddedAssets};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     /// Compile all pages on startup
-    mode: Mode,
-    /// The command in the $PATH that is assumed to run for web project. e.g. pnpm, npm, yarn, etc.
-    command: String,
-    /// The subcommand for `self.command` that will install dependencies.
+    pub(crate) mode: Mode,
+    /// The package manager that installs dependencies and runs scripts.
+    pub(crate) package_manager: PackageManager,
+    /// The subcommand for the package manager that will install dependencies.
     install_command: String,
+    /// Install dependencies even if the lockfile did not change since the last install.
+    #[serde(default)]
+    force_install: bool,
+    /// Fail instead of updating an outdated lockfile, e.g. for CI.
+    #[serde(default)]
+    frozen_install: bool,
+    /// The script that runs the dev server.
+    #[serde(default = "default_dev_script")]
+    dev_script: String,
+    /// The script that builds the production assets.
+    #[serde(default = "default_build_script")]
+    build_script: String,
+    /// Globs, relative to `root`, of the files the build depends on. The build is skipped if none
+    /// of them changed since the last successful build.
+    #[serde(default = "default_build_inputs")]
+    build_inputs: Vec<String>,
+    /// Write brotli, gzip and zstd variants of compressible files in `target` after building,
+    /// and serve them to clients that accept them.
+    #[serde(default = "default_precompress")]
+    precompress: bool,
     /// Directory to execute the command in.
     root: PathBuf,
     /// Path for the output files
-    target: PathBuf,
+    pub(crate) target: PathBuf,
     /// Dev server port to proxy.
     dev_server_port: u32,
+    /// Unix domain socket the dev server listens on, which is proxied instead of
+    /// `dev_server_port`.
+    #[cfg(unix)]
+    #[serde(default)]
+    dev_server_socket: Option<PathBuf>,
+    /// How the dev server is verified if it is served over HTTPS.
+    #[cfg(feature = "https")]
+    #[serde(skip)]
+    dev_server_https: Option<HttpsOptions>,
+    /// How the dev server is restarted when it exits unexpectedly.
+    #[serde(default)]
+    pub(crate) restart_policy: RestartPolicy,
+    /// How long the dev server gets to exit after SIGTERM before it is killed.
+    #[serde(default = "default_shutdown_grace_period")]
+    pub(crate) shutdown_grace_period: Duration,
+    /// How the dev server is probed before requests are forwarded to it.
+    #[serde(default)]
+    pub(crate) readiness: Readiness,
+    /// Retarget the proxy to the URL the dev server prints, e.g. when Vite moves to another port.
+    #[serde(default = "default_detect_dev_server_url")]
+    detect_dev_server_url: bool,
+    /// Additional regular expressions for finding the dev server URL in its output.
+    #[serde(default)]
+    dev_server_url_patterns: Vec<String>,
+    /// Assign a free port to the dev server instead of using `dev_server_port`.
+    #[serde(default)]
+    ephemeral_port: Option<PortPassing>,
+    /// Peers whose `X-Forwarded-For` header is forwarded to the dev server, e.g. a load balancer
+    /// in front of the server.
+    #[serde(default)]
+    trusted_proxies: Vec<IpAddr>,
+    /// Which headers tell the dev server about the original request.
+    #[serde(skip)]
+    forwarded_headers: ForwardedHeaders,
+    /// How request paths are resolved to files in [`Mode::Production`].
+    #[serde(default)]
+    routing: Routing,
+    /// The `Cache-Control` headers of files served in [`Mode::Production`].
+    #[serde(default)]
+    cache_policy: CachePolicy,
+    /// Serve a fallback file for client-side routes in [`Mode::Production`].
+    #[serde(default)]
+    spa_fallback: Option<SpaFallback>,
+    /// The path the frontend is mounted at, e.g. `/app/`. It is removed from request paths before
+    /// they are resolved to files. The dev and build scripts get it as `VITE_BASE`, so the dev
+    /// server is sent the prefixed paths.
+    #[serde(default = "default_base_path")]
+    pub(crate) base_path: String,
+    /// Add `integrity` attributes to the scripts and stylesheets of HTML documents, and to the
+    /// tags rendered by [`Manifest`](crate::Manifest), in [`Mode::Production`].
+    #[serde(default)]
+    pub(crate) subresource_integrity: bool,
+    /// The `Content-Security-Policy` of HTML documents, in both modes.
+    #[serde(default)]
+    content_security_policy: Option<ContentSecurityPolicy>,
+    /// How the output of spawned commands is written.
+    #[serde(skip)]
+    pub(crate) output_format: OutputFormat,
+    /// Assets to serve in production instead of reading them from `target`.
+    #[cfg(feature = "embed")]
+    #[serde(skip)]
+    pub(crate) embedded: Option<&'static EmbeddedAssets>,
+}
+
+/// How the output of spawned commands is written.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) enum OutputFormat {
+    /// Write to stdout and stderr, prefixing each line with the command.
+    #[default]
+    Prefixed,
+    /// Write each line as a `cargo:warning` directive, for use in build scripts.
+    #[cfg_attr(not(feature = "build"), allow(dead_code))]
+    CargoWarning,
+}
+
+fn default_dev_script() -> String {
+    "dev".into()
+}
+
+fn default_build_script() -> String {
+    "build".into()
+}
+
+fn default_build_inputs() -> Vec<String> {
+    vec!["**/*".into()]
+}
+
+fn default_base_path() -> String {
+    "/".into()
+}
+
+fn default_precompress() -> bool {
+    true
+}
+
+fn default_shutdown_grace_period() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_detect_dev_server_url() -> bool {
+    true
 }
 
 impl Config {
-    pub fn new_pnpm(mode: Mode, root: impl Into<PathBuf>) -> Self {
+    pub fn new(mode: Mode, root: impl Into<PathBuf>, package_manager: PackageManager) -> Self {
         let root = root.into();
 
         Self {
             mode,
-            command: "pnpm".into(),
+            package_manager,
             install_command: "install".into(),
+            force_install: false,
+            frozen_install: false,
+            dev_script: default_dev_script(),
+            build_script: default_build_script(),
+            build_inputs: default_build_inputs(),
+            precompress: default_precompress(),
             target: root.join("dist"),
             root,
             dev_server_port: 3000,
+            #[cfg(unix)]
+            dev_server_socket: None,
+            #[cfg(feature = "https")]
+            dev_server_https: None,
+            restart_policy: RestartPolicy::default(),
+            shutdown_grace_period: default_shutdown_grace_period(),
+            readiness: Readiness::default(),
+            detect_dev_server_url: default_detect_dev_server_url(),
+            dev_server_url_patterns: Vec::new(),
+            ephemeral_port: None,
+            trusted_proxies: Vec::new(),
+            forwarded_headers: ForwardedHeaders::default(),
+            routing: Routing::default(),
+            cache_policy: CachePolicy::default(),
+            spa_fallback: None,
+            base_path: default_base_path(),
+            subresource_integrity: false,
+            content_security_policy: None,
+            output_format: OutputFormat::default(),
+            #[cfg(feature = "embed")]
+            embedded: None,
         }
     }
 
+    /// Use the package manager whose lockfile is in `root`, falling back to npm.
+    pub fn new_detected(mode: Mode, root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+
+        let package_manager = PackageManager::detect(&root).unwrap_or_else(|| {
+            tracing::debug!("no lockfile found in {}, using npm", root.display());
+
+            PackageManager::Npm
+        });
+
+        Self::new(mode, root, package_manager)
+    }
+
+    pub fn new_npm(mode: Mode, root: impl Into<PathBuf>) -> Self {
+        Self::new(mode, root, PackageManager::Npm)
+    }
+
+    pub fn new_pnpm(mode: Mode, root: impl Into<PathBuf>) -> Self {
+        Self::new(mode, root, PackageManager::Pnpm)
+    }
+
+    /// Use Yarn, telling 1.x and 2+ apart by the project in `root`.
+    pub fn new_yarn(mode: Mode, root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let package_manager = PackageManager::detect_yarn(&root);
+
+        Self::new(mode, root, package_manager)
+    }
+
+    pub fn new_bun(mode: Mode, root: impl Into<PathBuf>) -> Self {
+        Self::new(mode, root, PackageManager::Bun)
+    }
+
+    pub fn new_deno(mode: Mode, root: impl Into<PathBuf>) -> Self {
+        Self::new(mode, root, PackageManager::Deno)
+    }
+
     pub fn root(mut self, value: impl Into<PathBuf>) -> Self {
-        self.target = value.into();
+        self.root = value.into();
+
+        self
+    }
+
+    pub fn package_manager(mut self, value: PackageManager) -> Self {
+        self.package_manager = value;
+
+        self
+    }
+
+    pub fn install_command(mut self, value: impl Into<String>) -> Self {
+        self.install_command = value.into();
+
+        self
+    }
+
+    pub fn force_install(mut self, value: bool) -> Self {
+        self.force_install = value;
+
+        self
+    }
+
+    pub fn frozen_install(mut self, value: bool) -> Self {
+        self.frozen_install = value;
+
+        self
+    }
+
+    pub fn dev_script(mut self, value: impl Into<String>) -> Self {
+        self.dev_script = value.into();
+
+        self
+    }
+
+    pub fn build_script(mut self, value: impl Into<String>) -> Self {
+        self.build_script = value.into();
+
+        self
+    }
+
+    /// Replace the globs of files the build depends on. `node_modules` and `target` are never
+    /// considered inputs.
+    pub fn build_inputs<I, S>(mut self, value: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.build_inputs = value.into_iter().map(Into::into).collect();
+
+        self
+    }
+
+    pub fn precompress(mut self, value: bool) -> Self {
+        self.precompress = value;
 
         self
     }
@@ -67,6 +338,225 @@ impl Config {
         self
     }
 
+    /// Proxy to the dev server over the Unix domain socket at `path`, which the dev script has
+    /// to listen on. This overrides [`Self::dev_server_port`] and [`Self::ephemeral_port`], and
+    /// the dev server URL is not detected from its output. A socket nothing listens on anymore
+    /// is removed before the dev server is started.
+    #[cfg(unix)]
+    pub fn dev_server_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dev_server_socket = Some(path.into());
+
+        self
+    }
+
+    /// Proxy to a dev server that is served over HTTPS, e.g. Vite with `@vitejs/plugin-basic-ssl`,
+    /// verified according to `options`. The dev server is expected at
+    /// `https://localhost:<port>`, and `https` URLs are detected from its output.
+    #[cfg(feature = "https")]
+    pub fn dev_server_https(mut self, options: HttpsOptions) -> Self {
+        self.dev_server_https = Some(options);
+
+        self
+    }
+
+    pub fn restart_policy(mut self, value: RestartPolicy) -> Self {
+        self.restart_policy = value;
+
+        self
+    }
+
+    pub fn shutdown_grace_period(mut self, value: Duration) -> Self {
+        self.shutdown_grace_period = value;
+
+        self
+    }
+
+    pub fn readiness(mut self, value: Readiness) -> Self {
+        self.readiness = value;
+
+        self
+    }
+
+    pub fn detect_dev_server_url(mut self, value: bool) -> Self {
+        self.detect_dev_server_url = value;
+
+        self
+    }
+
+    /// Add a regular expression that finds the dev server URL in its output. The first capture
+    /// group, or the group named `url`, has to match a URL like `http://localhost:5173` or a port.
+    pub fn dev_server_url_pattern(mut self, value: impl Into<String>) -> Self {
+        self.dev_server_url_patterns.push(value.into());
+
+        self
+    }
+
+    /// Run the dev server on a free port chosen when the [`WebdevService`] is created. This
+    /// overrides [`Self::dev_server_port`].
+    pub fn ephemeral_port(mut self, value: PortPassing) -> Self {
+        self.ephemeral_port = Some(value);
+
+        self
+    }
+
+    /// Keep the `X-Forwarded-For` header of requests from `proxy` when forwarding them to the
+    /// dev server. The header of other peers is replaced with their address.
+    pub fn trusted_proxy(mut self, proxy: IpAddr) -> Self {
+        self.trusted_proxies.push(proxy);
+
+        self
+    }
+
+    /// Which headers tell the dev server about the original request, e.g. so it can build
+    /// absolute URLs. See [`ForwardedHeaders`].
+    pub fn forwarded_headers(mut self, value: ForwardedHeaders) -> Self {
+        self.forwarded_headers = value;
+
+        self
+    }
+
+    /// Resolve clean URLs and trailing slashes, and serve a custom not found page, in
+    /// [`Mode::Production`].
+    pub fn routing(mut self, value: Routing) -> Self {
+        self.routing = value;
+
+        self
+    }
+
+    pub fn cache_policy(mut self, value: CachePolicy) -> Self {
+        self.cache_policy = value;
+
+        self
+    }
+
+    /// Serve `index.html`, or another file, for client-side routes of a single page app in
+    /// [`Mode::Production`].
+    pub fn spa_fallback(mut self, value: SpaFallback) -> Self {
+        self.spa_fallback = Some(value);
+
+        self
+    }
+
+    /// Mount the frontend at `path`, e.g. `/app`, when other routes of the server own `/`.
+    ///
+    /// Requests outside of it are answered with `404 Not Found`. The dev and build scripts get
+    /// the path, with a trailing slash, as `VITE_BASE` to use as Vite's `base` option, so the
+    /// dev server serves the prefixed paths itself. In production the prefix is removed to
+    /// resolve files, and added back to `Location` headers.
+    pub fn base_path(mut self, path: impl AsRef<str>) -> Self {
+        self.base_path = base_path::normalize(path.as_ref());
+
+        self
+    }
+
+    /// Add `integrity="sha384-..."` to the `<script src>` and `<link rel=stylesheet>` tags that
+    /// reference files in `target`, in [`Mode::Production`]. See [`Integrity`].
+    pub fn subresource_integrity(mut self, value: bool) -> Self {
+        self.subresource_integrity = value;
+
+        self
+    }
+
+    /// Send a `Content-Security-Policy` with HTML documents, and add a nonce to their `<script>`
+    /// and `<style>` tags. See [`ContentSecurityPolicy`].
+    pub fn content_security_policy(mut self, value: ContentSecurityPolicy) -> Self {
+        self.content_security_policy = Some(value);
+
+        self
+    }
+
+    /// Serve these assets, included with [`embed_assets!`](crate::embed_assets), in
+    /// [`Mode::Production`] instead of reading `target` from disk.
+    #[cfg(feature = "embed")]
+    pub fn embedded(mut self, value: &'static EmbeddedAssets) -> Self {
+        self.embedded = Some(value);
+
+        self
+    }
+
+    /// Whether production assets are served from the binary rather than from `target`.
+    fn serves_embedded(&self) -> bool {
+        #[cfg(feature = "embed")]
+        let embedded = self.embedded.is_some();
+
+        #[cfg(not(feature = "embed"))]
+        let embedded = false;
+
+        embedded
+    }
+
+    /// Pick a free port for the dev server if [`Self::ephemeral_port`] is set.
+    fn assign_port(&mut self) -> Result<(), WebdevError> {
+        if self.ephemeral_port.is_none() || self.dev_server_socket_path().is_some() {
+            return Ok(());
+        }
+
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+        self.dev_server_port = listener.local_addr()?.port().into();
+
+        tracing::debug!("assigned port {} to the dev server", self.dev_server_port);
+
+        Ok(())
+    }
+
+    pub(crate) fn dev_server_url_matcher(&self) -> Result<Option<UrlMatcher>, WebdevError> {
+        if !self.detect_dev_server_url || self.dev_server_socket_path().is_some() {
+            return Ok(None);
+        }
+
+        UrlMatcher::new(&self.dev_server_url_patterns, self.dev_server_scheme()).map(Some)
+    }
+
+    pub(crate) fn dev_server_url(&self) -> String {
+        if self.dev_server_socket_path().is_some() {
+            return "http://localhost".into();
+        }
+
+        format!(
+            "{}://localhost:{}",
+            self.dev_server_scheme(),
+            self.dev_server_port
+        )
+    }
+
+    fn dev_server_scheme(&self) -> &'static str {
+        #[cfg(feature = "https")]
+        let https = self.dev_server_https.is_some();
+
+        #[cfg(not(feature = "https"))]
+        let https = false;
+
+        if https {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// How the dev server is connected to, besides its URL.
+    pub(crate) fn dev_server_connection(&self) -> Connection<'_> {
+        if let Some(socket) = self.dev_server_socket_path() {
+            return Connection::Unix(socket);
+        }
+
+        #[cfg(feature = "https")]
+        if let Some(options) = &self.dev_server_https {
+            return Connection::Https(options);
+        }
+
+        Connection::Tcp
+    }
+
+    pub(crate) fn dev_server_socket_path(&self) -> Option<&Path> {
+        #[cfg(unix)]
+        let socket = self.dev_server_socket.as_deref();
+
+        #[cfg(not(unix))]
+        let socket = None;
+
+        socket
+    }
+
     fn ensure_target_exists(&self) -> std::io::Result<()> {
         std::fs::create_dir_all(&self.target)
     }
@@ -90,9 +580,36 @@ impl Mode {
     }
 }
 
+/// How an automatically assigned port is passed to the dev server.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum PortPassing {
+    /// Set the environment variable with this name to the port.
+    Env(String),
+    /// Append these arguments to the dev command, replacing `{port}` with the port.
+    Args(Vec<String>),
+}
+
+impl PortPassing {
+    /// Pass the port through the `PORT` environment variable.
+    pub fn env() -> Self {
+        Self::Env("PORT".into())
+    }
+
+    /// Pass the port as `--port {port}`.
+    pub fn port_arg() -> Self {
+        Self::Args(vec!["--port".into(), "{port}".into()])
+    }
+}
+
 pub struct WebdevService<B> {
     config: Config,
     inner_service: InnerService<B>,
+    cache_rules: Arc<CacheRules>,
+    html_transform: Option<Arc<dyn HtmlTransform>>,
+    integrity: Option<Integrity>,
+    dev_server: Option<Arc<Supervisor>>,
+    /// Resolves once the dev server is ready or the readiness timeout passed.
+    pending_ready: Mutex<Option<BoxFuture<'static, ()>>>,
 }
 
 impl<B> Clone for WebdevService<B> {
@@ -100,40 +617,146 @@ impl<B> Clone for WebdevService<B> {
         WebdevService {
             config: self.config.clone(),
             inner_service: self.inner_service.clone(),
+            cache_rules: self.cache_rules.clone(),
+            html_transform: self.html_transform.clone(),
+            integrity: self.integrity.clone(),
+            dev_server: self.dev_server.clone(),
+            pending_ready: Mutex::new(None),
         }
     }
 }
 
 impl<B> WebdevService<B> {
-    pub async fn new(config: Config) -> Result<Self, std::io::Error>
+    pub async fn new(mut config: Config) -> Result<Self, WebdevError>
     where
         B: HttpBody + Send + Unpin + 'static,
         B::Data: Send,
         B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
     {
-        config.ensure_target_exists()?;
+        if !config.serves_embedded() {
+            config.ensure_target_exists()?;
+        }
+
+        if let Mode::Development = config.mode {
+            config.assign_port()?;
+        }
+
+        config.base_path = base_path::normalize(&config.base_path);
+
+        if let Some(policy) = &config.content_security_policy {
+            policy.validate()?;
+        }
 
-        let this = Self {
-            inner_service: InnerService::from_config(&config),
+        let mut this = Self {
+            inner_service: InnerService::from_config(&config)?,
+            cache_rules: Arc::new(config.cache_policy.compile()?),
+            html_transform: None,
+            integrity: None,
             config,
+            dev_server: None,
+            pending_ready: Mutex::new(None),
         };
 
         match &this.config.mode {
             Mode::Development => {
+                let url_matcher = this.config.dev_server_url_matcher()?;
+
                 this.config.execute_install().await?;
-                this.config.execute_dev().await?;
+                this.dev_server = Some(Arc::new(Supervisor::spawn(
+                    this.config.clone(),
+                    url_matcher,
+                )));
             }
             Mode::Production => {
                 // this.config.execute_install().await?;
                 // this.config.execute_build().await?;
+
+                if this.config.subresource_integrity {
+                    let integrity = Integrity::new(&this.config);
+
+                    tokio::task::spawn_blocking({
+                        let integrity = integrity.clone();
+                        move || integrity.warm_up()
+                    })
+                    .await
+                    .expect("computing digests does not panic");
+
+                    this.integrity = Some(integrity);
+                }
             }
         }
 
         Ok(this)
     }
+
+    /// Rewrite HTML documents, both from the dev server and from the production build, while they
+    /// are streamed to the client.
+    ///
+    /// Requests that accept HTML are forwarded without their `Accept-Encoding` header, since
+    /// compressed documents cannot be rewritten.
+    pub fn html_transform(mut self, transform: impl HtmlTransform) -> Self {
+        self.html_transform = Some(Arc::new(transform));
+
+        self
+    }
+
+    /// Determine the client address forwarded to the dev server with `extractor` instead of
+    /// [`PeerAddr`](crate::PeerAddr), e.g. when the server listens on something other than TCP.
+    pub fn client_ip_extractor(mut self, extractor: impl ClientIpExtractor) -> Self {
+        self.inner_service = match self.inner_service {
+            InnerService::ReverseProxy(proxy) => {
+                InnerService::ReverseProxy(Box::new(proxy.client_ip_extractor(extractor)))
+            }
+            #[cfg(unix)]
+            InnerService::UnixProxy(proxy) => {
+                InnerService::UnixProxy(Box::new(proxy.client_ip_extractor(extractor)))
+            }
+            #[cfg(feature = "https")]
+            InnerService::HttpsProxy(proxy) => {
+                InnerService::HttpsProxy(Box::new(proxy.client_ip_extractor(extractor)))
+            }
+            service => service,
+        };
+
+        self
+    }
+
+    /// The digests added to HTML documents, if [`Config::subresource_integrity`] is enabled in
+    /// [`Mode::Production`]. Server-rendered templates can use them for their own tags.
+    pub fn integrity(&self) -> Option<&Integrity> {
+        self.integrity.as_ref()
+    }
+
+    /// Watch the state of the supervised dev server. Returns `None` outside of [`Mode::Development`].
+    pub fn dev_server_state(&self) -> Option<watch::Receiver<DevServerState>> {
+        self.dev_server
+            .as_ref()
+            .map(|dev_server| dev_server.state())
+    }
+
+    /// A handle that stops the dev server, e.g. from a graceful shutdown signal.
+    ///
+    /// The dev server is also stopped once the last clone of this service is dropped.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.dev_server.as_deref())
+    }
+
+    /// Resolve once the dev server accepts requests. Resolves immediately outside of
+    /// [`Mode::Development`].
+    pub async fn ready(&self) -> Result<(), WebdevError> {
+        match &self.dev_server {
+            Some(dev_server) => dev_server.ready().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the dev server and wait for it to exit. See [`Self::shutdown_handle`].
+    pub async fn shutdown(&self) {
+        self.shutdown_handle().shutdown().await
+    }
 }
 
-pub type WebdevResponse = Either<ServeFileSystemResponseBody, InsecureReverseProxyServiceBody>;
+pub type WebdevResponse = WebdevBody;
 
 impl<Body> Service<Request<Body>> for WebdevService<Body>
 where
@@ -145,41 +768,291 @@ where
     type Error = std::convert::Infallible;
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
+    /// In [`Mode::Development`] this waits for the dev server to become ready, for at most
+    /// [`Readiness::request_timeout`].
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(dev_server) = self.dev_server.as_ref().filter(|d| !d.is_ready()) {
+            let timeout = self.config.readiness.request_timeout;
+            let pending_ready = self
+                .pending_ready
+                .get_mut()
+                .unwrap()
+                .get_or_insert_with(|| {
+                    let ready = dev_server.ready();
+
+                    Box::pin(async move {
+                        if tokio::time::timeout(timeout, ready).await.is_err() {
+                            tracing::warn!("dev server did not become ready within {:?}", timeout);
+                        }
+                    })
+                });
+
+            ready!(pending_ready.as_mut().poll(cx));
+        }
+
+        *self.pending_ready.get_mut().unwrap() = None;
+
         match &mut self.inner_service {
             InnerService::ServeDir(serve_dir) => {
                 <ServeDir as Service<Request<Body>>>::poll_ready(serve_dir, cx)
             }
-            InnerService::ReverseProxy(proxy) => {
-                <HttpReverseProxyService<Body> as Service<Request<Body>>>::poll_ready(proxy, cx)
-            }
+            InnerService::ReverseProxy(proxy) => <HttpReverseProxyService<Body> as Service<
+                Request<Body>,
+            >>::poll_ready(&mut **proxy, cx),
+            #[cfg(unix)]
+            InnerService::UnixProxy(proxy) => <UnixReverseProxyService<Body> as Service<
+                Request<Body>,
+            >>::poll_ready(&mut **proxy, cx),
+            #[cfg(feature = "https")]
+            InnerService::HttpsProxy(proxy) => <HttpsReverseProxyService<Body> as Service<
+                Request<Body>,
+            >>::poll_ready(&mut **proxy, cx),
+            #[cfg(feature = "embed")]
+            InnerService::Embedded(_) => Poll::Ready(Ok(())),
         }
     }
 
     fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if self.html_transform.is_none()
+            && self.config.content_security_policy.is_none()
+            && self.integrity.is_none()
+        {
+            return self.serve(request);
+        }
+
+        let transform = self.html_transform.clone();
+        let integrity = self.integrity.clone();
+        let base = self.config.base_path.clone();
+        let (mut parts, body) = request.into_parts();
+
+        let csp = self.config.content_security_policy.as_ref().map(|policy| {
+            let nonce = CspNonce::generate();
+            let dev_sources = self
+                .dev_server
+                .as_ref()
+                .map(|dev_server| {
+                    csp::dev_sources(&dev_server.target(), parts.headers.get(header::HOST))
+                })
+                .unwrap_or_default();
+
+            parts.extensions.insert(nonce.clone());
+
+            (
+                policy.header_name(),
+                policy.header_value(&nonce, &dev_sources),
+                nonce,
+            )
+        });
+
+        let original = parts.clone();
+
+        // Compressed documents cannot be rewritten.
+        if spa::accepts_html(&parts.headers) {
+            parts.headers.remove(header::ACCEPT_ENCODING);
+        }
+
+        let future = self.serve(Request::from_parts(parts, body));
+
+        Box::pin(async move {
+            let Ok(res) = future.await;
+
+            let mut res = transform::apply(&original, res, |html| {
+                if let Some(transform) = &transform {
+                    transform.transform(&original, html);
+                }
+
+                if let Some((_, _, nonce)) = &csp {
+                    csp::add_nonce(nonce, html);
+                }
+
+                if let Some(integrity) = &integrity {
+                    integrity::add_integrity(integrity, &base, &original.uri, html);
+                }
+            });
+
+            // Only rewritten documents carry the nonce.
+            if let Some((name, value, _)) = csp {
+                if matches!(res.body(), WebdevBody::Html(_)) {
+                    res.headers_mut().insert(name, value);
+                }
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+impl<Body> WebdevService<Body>
+where
+    Body: HttpBody + Send + Unpin + 'static,
+    Body::Data: Send,
+    Body::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    fn serve(
+        &self,
+        mut request: Request<Body>,
+    ) -> BoxFuture<'static, Result<Response<WebdevBody>, std::convert::Infallible>> {
+        let base = &self.config.base_path;
+
+        // The dev server gets the base as `VITE_BASE` and serves the prefixed paths itself.
+        if let Mode::Development = self.config.mode {
+            if let Some(res) = base_path::reject(base, request.uri()) {
+                return Box::pin(std::future::ready(Ok(res)));
+            }
+
+            return self.serve_unprefixed(request);
+        }
+
+        if let Some(res) = base_path::strip(base, &mut request) {
+            return Box::pin(std::future::ready(Ok(res)));
+        }
+
+        if base == "/" {
+            return self.serve_unprefixed(request);
+        }
+
+        let base = base.clone();
+        let future = self.serve_unprefixed(request);
+
+        Box::pin(async move {
+            let Ok(mut res) = future.await;
+            base_path::rewrite_location(&base, &mut res);
+
+            Ok(res)
+        })
+    }
+
+    /// Serve a request whose path is relative to the base path, or in [`Mode::Development`] still
+    /// prefixed with it.
+    fn serve_unprefixed(
+        &self,
+        request: Request<Body>,
+    ) -> BoxFuture<'static, Result<Response<WebdevBody>, std::convert::Infallible>> {
+        let fallback = self.config.fallback(&request);
+
         match &self.inner_service {
+            #[cfg(feature = "embed")]
+            InnerService::Embedded(assets) => {
+                let routing = &self.config.routing;
+                let cache_rules = &self.cache_rules;
+
+                let res = match (
+                    embed::serve(assets, routing, cache_rules, &request),
+                    fallback,
+                ) {
+                    (res, Some(fallback)) if res.status() == StatusCode::NOT_FOUND => fallback
+                        .respond(embed::serve(
+                            assets,
+                            routing,
+                            cache_rules,
+                            &fallback.request,
+                        )),
+                    (res, _) => res,
+                };
+
+                Box::pin(std::future::ready(Ok(res)))
+            }
             InnerService::ServeDir(serve_dir) => {
                 let mut serve_dir = serve_dir.clone();
+                let routing = self.config.routing.clone();
+                let target = self.config.target.clone();
+                let cache_rules = self.cache_rules.clone();
 
                 Box::pin(async move {
-                    let res = serve_dir.call(request).await.unwrap();
+                    let mut request = request;
+                    let if_none_match = request
+                        .headers()
+                        .get_all(header::IF_NONE_MATCH)
+                        .iter()
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+                    if request.method() == Method::GET || request.method() == Method::HEAD {
+                        match routing.resolve_on_disk(&target, request.uri()).await {
+                            Route::File(file) => {
+                                *request.uri_mut() =
+                                    routing::file_uri(&file, request.uri().query());
+                            }
+                            Route::Redirect { trailing_slash } => {
+                                return Ok(routing::redirect(request.uri(), trailing_slash));
+                            }
+                            Route::NotFound => {}
+                        }
+                    }
+
+                    let mut path = request.uri().path().to_owned();
+                    let Ok(res) = serve_dir.call(request).await;
+
+                    let res = match fallback {
+                        Some(fallback) if res.status() == StatusCode::NOT_FOUND => {
+                            path = fallback.request.uri().path().to_owned();
+                            let Ok(res) = serve_dir.call(fallback.request.clone()).await;
 
-                    Ok(res.map(Either::Left))
+                            fallback.respond(res)
+                        }
+                        _ => res,
+                    };
+
+                    let mut res = res.map(WebdevBody::ServeDir);
+
+                    // `ServeDir` does not tell caches that precompressed variants depend on the
+                    // request's `Accept-Encoding`.
+                    if res.headers().contains_key(header::CONTENT_ENCODING) {
+                        res.headers_mut()
+                            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+                    }
+
+                    let path = percent_decode_str(&path).decode_utf8_lossy();
+
+                    Ok(cache_rules.apply(&path, &if_none_match, res))
                 })
             }
+            // Only the proxies have a dev server.
+            _ if self.dev_server.as_ref().is_some_and(|d| !d.is_ready()) => Box::pin(async move {
+                let res = Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(WebdevBody::full(
+                        "Service unavailable. Your dev server is not ready yet.",
+                    ))
+                    .unwrap();
+
+                Ok(res)
+            }),
             InnerService::ReverseProxy(proxy) => {
                 let mut proxy = proxy.clone();
 
+                if let Some(dev_server) = &self.dev_server {
+                    proxy.target = dev_server.target();
+                }
+
                 Box::pin(async move {
-                    let res = proxy
-                        .call(request)
-                        .await
-                        .inspect_err(|err| {
-                            tracing::error!("gaaaaah: {}", err);
-                        })
-                        .unwrap();
-
-                    Ok(res.map(Either::Right))
+                    let Ok(res) = proxy.call(request).await;
+
+                    Ok(res.map(WebdevBody::Proxy))
+                })
+            }
+            #[cfg(feature = "https")]
+            InnerService::HttpsProxy(proxy) => {
+                let mut proxy = proxy.clone();
+
+                if let Some(dev_server) = &self.dev_server {
+                    proxy.target = dev_server.target();
+                }
+
+                Box::pin(async move {
+                    let Ok(res) = proxy.call(request).await;
+
+                    Ok(res.map(WebdevBody::Proxy))
+                })
+            }
+            #[cfg(unix)]
+            InnerService::UnixProxy(proxy) => {
+                let mut proxy = proxy.clone();
+
+                Box::pin(async move {
+                    let Ok(res) = proxy.call(request).await;
+
+                    Ok(res.map(WebdevBody::Proxy))
                 })
             }
         }
@@ -187,141 +1060,496 @@ where
 }
 
 enum InnerService<Body> {
-    ReverseProxy(HttpReverseProxyService<Body>),
+    ReverseProxy(Box<HttpReverseProxyService<Body>>),
+    #[cfg(unix)]
+    UnixProxy(Box<UnixReverseProxyService<Body>>),
+    #[cfg(feature = "https")]
+    HttpsProxy(Box<HttpsReverseProxyService<Body>>),
     ServeDir(ServeDir),
+    #[cfg(feature = "embed")]
+    Embedded(&'static EmbeddedAssets),
 }
 
 impl<B> Clone for InnerService<B> {
     fn clone(&self) -> Self {
         match self {
             Self::ReverseProxy(p) => Self::ReverseProxy(p.clone()),
+            #[cfg(unix)]
+            Self::UnixProxy(p) => Self::UnixProxy(p.clone()),
+            #[cfg(feature = "https")]
+            Self::HttpsProxy(p) => Self::HttpsProxy(p.clone()),
             Self::ServeDir(s) => Self::ServeDir(s.clone()),
+            #[cfg(feature = "embed")]
+            Self::Embedded(e) => Self::Embedded(e),
         }
     }
 }
 
 impl<Body> InnerService<Body> {
-    fn from_config(config: &Config) -> Self
+    fn from_config(config: &Config) -> Result<Self, WebdevError>
     where
         Body: HttpBody + Send + Unpin + 'static,
         Body::Data: Send,
     {
-        match &config.mode {
-            Mode::Development => Self::ReverseProxy(InsecureReverseProxyService::new_http(
-                format!("http://localhost:{}", config.dev_server_port),
-            )),
+        let service = match &config.mode {
+            #[cfg(unix)]
+            Mode::Development if config.dev_server_socket.is_some() => {
+                let socket = config.dev_server_socket.clone().expect("socket is set");
+
+                Self::UnixProxy(Box::new(
+                    InsecureReverseProxyService::new_unix(socket)
+                        .trusted_proxies(config.trusted_proxies.iter().copied())
+                        .forwarded_headers(config.forwarded_headers.clone()),
+                ))
+            }
+            #[cfg(feature = "https")]
+            Mode::Development if config.dev_server_https.is_some() => {
+                let target = config.dev_server_url();
+                target.parse::<Uri>().map_err(ProxyError::from)?;
+                let options = config.dev_server_https.clone().expect("options are set");
+
+                Self::HttpsProxy(Box::new(
+                    InsecureReverseProxyService::new_https(target, options)?
+                        .trusted_proxies(config.trusted_proxies.iter().copied())
+                        .forwarded_headers(config.forwarded_headers.clone()),
+                ))
+            }
+            Mode::Development => {
+                let target = config.dev_server_url();
+                target.parse::<Uri>().map_err(ProxyError::from)?;
+
+                Self::ReverseProxy(Box::new(
+                    InsecureReverseProxyService::new_http(target)
+                        .trusted_proxies(config.trusted_proxies.iter().copied())
+                        .forwarded_headers(config.forwarded_headers.clone()),
+                ))
+            }
+            #[cfg(feature = "embed")]
+            Mode::Production if config.embedded.is_some() => {
+                Self::Embedded(config.embedded.expect("embedded assets are set"))
+            }
             _ => {
-                let serve_dir = ServeDir::new(&config.target);
+                let mut serve_dir = ServeDir::new(&config.target);
+
+                if config.precompress {
+                    serve_dir = serve_dir
+                        .precompressed_br()
+                        .precompressed_zstd()
+                        .precompressed_gzip();
+                }
 
                 Self::ServeDir(serve_dir)
             }
+        };
+
+        Ok(service)
+    }
+}
+
+/// A file that is served instead when a request does not resolve to a file of the production
+/// build.
+struct Fallback {
+    request: Request<()>,
+    /// Whether this is the not found page, which keeps the `404 Not Found` status.
+    not_found: bool,
+}
+
+impl Fallback {
+    fn respond<T>(&self, mut res: Response<T>) -> Response<T> {
+        if self.not_found {
+            *res.status_mut() = StatusCode::NOT_FOUND;
+        }
+
+        res
+    }
+}
+
+impl Config {
+    fn fallback<B>(&self, request: &Request<B>) -> Option<Fallback> {
+        if let Some(request) = self
+            .spa_fallback
+            .as_ref()
+            .and_then(|spa_fallback| spa_fallback.request(request))
+        {
+            return Some(Fallback {
+                request,
+                not_found: false,
+            });
         }
+
+        self.routing
+            .not_found_request(request)
+            .map(|request| Fallback {
+                request,
+                not_found: true,
+            })
     }
 }
 
+/// How many lines of a command's output are kept for [`WebdevError::CommandFailed`].
+const CAPTURED_LINES: usize = 100;
+
+/// File in `node_modules`, or in the [`Config::stamp_dir`] without one, that records the
+/// fingerprint of the last successful install.
+const INSTALL_STAMP: &str = ".tower-webdev-install";
+
+/// File in the [`Config::stamp_dir`] that records the fingerprint of the last successful build.
+/// It is kept out of `target`, which is served as is.
+const BUILD_STAMP: &str = ".tower-webdev-build";
+
 #[allow(unused)]
 impl Config {
-    async fn execute_install(&self) -> Result<(), std::io::Error> {
-        let mut command = Command::new(&self.command);
-        command.current_dir(&self.root.canonicalize()?);
+    /// Install dependencies unless `package.json` and the lockfile are unchanged since the last
+    /// install.
+    async fn execute_install(&self) -> Result<(), WebdevError> {
+        let args = self
+            .package_manager
+            .install_args(&self.install_command, self.frozen_install);
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
 
-        command.args(["install"]);
-        command.stdout(Stdio::piped());
+        let root = self.root_dir()?;
 
-        let mut build_process = command.spawn()?;
+        if !self.force_install
+            && self
+                .install_stamp(&root)?
+                .matches(&self.install_fingerprint(&root, &args)?)
+        {
+            tracing::info!("dependencies are up to date, skipping install");
 
-        let stdout = build_process
-            .stdout
-            .take()
-            .expect("build_process did not have a handle to stdout");
+            return Ok(());
+        }
 
-        write_stdout(stdout, "install");
+        self.execute(self.command(&args)?, &args, "install").await?;
 
-        match build_process.wait().await {
-            Ok(status) => {
-                if !status.success() {
-                    tracing::error!("build process exited with error");
+        // Installing may update the lockfile, so fingerprint it afterwards.
+        self.install_stamp(&root)?
+            .save(&self.install_fingerprint(&root, &args)?);
 
-                    std::process::exit(status.code().unwrap_or(1));
-                }
-            }
-            Err(error) => {
-                tracing::error!("error waiting for build process: {}", error);
-            }
+        Ok(())
+    }
+
+    /// The stamp goes into `node_modules`, so that deleting it forces an install. Yarn PnP and
+    /// Deno do not create one.
+    fn install_stamp(&self, root: &Path) -> Result<Stamp, WebdevError> {
+        let node_modules = root.join("node_modules");
+
+        if node_modules.is_dir() {
+            Ok(Stamp::new(node_modules.join(INSTALL_STAMP)))
+        } else {
+            Ok(Stamp::new(self.stamp_dir(root)?.join(INSTALL_STAMP)))
+        }
+    }
+
+    /// A directory for stamps outside of the frontend: `OUT_DIR` in build scripts, the temporary
+    /// directory otherwise, with a subdirectory per `root` and `target`.
+    fn stamp_dir(&self, root: &Path) -> Result<PathBuf, WebdevError> {
+        let mut key = Fingerprint::new();
+        key.update(root.as_os_str().as_encoded_bytes());
+        key.update(self.target_dir()?.as_os_str().as_encoded_bytes());
+
+        let dir = std::env::var_os("OUT_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+
+        Ok(dir.join("tower-webdev").join(&key.finish()[..16]))
+    }
+
+    fn install_fingerprint(&self, root: &Path, args: &[&str]) -> Result<String, WebdevError> {
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.update(self.command_line(args));
+        fingerprint.update_file(root, Path::new("package.json"))?;
+
+        for lockfile in self.package_manager.lockfiles() {
+            fingerprint.update_file(root, Path::new(lockfile))?;
+        }
+
+        Ok(fingerprint.finish())
+    }
+
+    /// Build the production assets unless the build inputs are unchanged since the last
+    /// successful build.
+    async fn execute_build(&self) -> Result<(), WebdevError> {
+        let args = self.package_manager.run_args(&self.build_script, &[]);
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let root = self.root_dir()?;
+        let stamp = Stamp::new(self.stamp_dir(&root)?.join(BUILD_STAMP));
+        let fingerprint = self.build_fingerprint(&root, &args)?;
+
+        // The stamp is kept outside of `target`, which may have been deleted since.
+        if stamp.matches(&fingerprint) && self.has_build_output() {
+            tracing::info!("build inputs are unchanged, skipping build");
+
+            return Ok(());
         }
 
+        let mut command = self.command(&args)?;
+
+        if self.base_path != "/" {
+            command.env("VITE_BASE", &self.base_path);
+        }
+
+        self.execute(command, &args, "build").await?;
+
+        self.ensure_target_exists()?;
+
+        #[cfg(feature = "build")]
+        if self.precompress {
+            let target = self.target.clone();
+
+            tokio::task::spawn_blocking(move || crate::compress::precompress(&target))
+                .await
+                .map_err(std::io::Error::other)??;
+        }
+
+        stamp.save(&fingerprint);
+
         Ok(())
     }
 
-    async fn execute_build(&self) -> Result<(), std::io::Error> {
-        let mut command = Command::new(&self.command);
-        command.current_dir(&self.root.canonicalize()?);
+    /// Whether `target` exists and is not empty.
+    fn has_build_output(&self) -> bool {
+        std::fs::read_dir(&self.target).is_ok_and(|mut entries| entries.next().is_some())
+    }
 
-        command.args(["build"]);
-        command.stdout(Stdio::piped());
+    /// The absolute path of `target`, which does not need to exist yet.
+    pub(crate) fn target_dir(&self) -> Result<PathBuf, WebdevError> {
+        match self.target.canonicalize() {
+            Ok(target) => Ok(target),
+            Err(_) => Ok(std::path::absolute(&self.target)?),
+        }
+    }
 
-        let mut build_process = command.spawn()?;
+    fn build_fingerprint(&self, root: &Path, args: &[&str]) -> Result<String, WebdevError> {
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.update(self.command_line(args));
+        // The build gets the base as `VITE_BASE`.
+        fingerprint.update(&self.base_path);
+        // The compressed variants are written as part of the build.
+        fingerprint.update([u8::from(self.precompress)]);
 
-        let stdout = build_process
-            .stdout
-            .take()
-            .expect("build_process did not have a handle to stdout");
+        for path in self.build_input_files(root)? {
+            fingerprint.update_file(root, &path)?;
+        }
 
-        write_stdout(stdout, "build");
+        Ok(fingerprint.finish())
+    }
 
-        match build_process.wait().await {
-            Ok(status) => {
-                if !status.success() {
-                    tracing::error!("build process exited with error");
+    /// The files matching `build_inputs`, relative to `root` and in a stable order.
+    pub(crate) fn build_input_files(&self, root: &Path) -> Result<Vec<PathBuf>, WebdevError> {
+        let mut globs = GlobSetBuilder::new();
 
-                    std::process::exit(status.code().unwrap_or(1));
-                }
+        for input in &self.build_inputs {
+            globs.add(Glob::new(input)?);
+        }
+
+        let globs = globs.build()?;
+        let target = self.target_dir()?;
+
+        let entries = WalkDir::new(root)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| !is_excluded_dir(entry.path(), &target));
+
+        let mut files = Vec::new();
+
+        for entry in entries {
+            let entry = entry.map_err(std::io::Error::from)?;
+
+            if !entry.file_type().is_file() {
+                continue;
             }
-            Err(error) => {
-                tracing::error!("error waiting for build process: {}", error);
+
+            let path = entry
+                .path()
+                .strip_prefix(root)
+                .expect("walked path is inside of root");
+
+            if globs.is_match(path) {
+                files.push(path.to_owned());
             }
         }
 
+        Ok(files)
+    }
+
+    /// Spawn the dev server, calling `on_line` for every line of its output.
+    pub(crate) fn spawn_dev<F>(&self, on_line: F) -> Result<Child, WebdevError>
+    where
+        F: FnMut(&str) + Clone + Send + 'static,
+    {
+        let port = self.dev_server_port.to_string();
+
+        let port_args = match &self.ephemeral_port {
+            Some(PortPassing::Args(port_args)) => port_args
+                .iter()
+                .map(|arg| arg.replace("{port}", &port))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let args = self.package_manager.run_args(&self.dev_script, &port_args);
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        let mut command = self.command(&args)?;
+
+        if let Some(PortPassing::Env(name)) = &self.ephemeral_port {
+            command.env(name, &port);
+        }
+
+        if self.base_path != "/" {
+            command.env("VITE_BASE", &self.base_path);
+        }
+
+        // A dev server that was killed leaves its socket behind, which it would fail to listen on
+        // when it is restarted.
+        #[cfg(unix)]
+        if let Some(socket) = self.dev_server_socket_path() {
+            remove_stale_socket(socket);
+        }
+
+        // Give the dev server its own process group so it can be signalled together with any
+        // processes it spawns itself.
+        #[cfg(unix)]
+        command.process_group(0);
+        command.kill_on_drop(true);
+        // Outside of the terminal's foreground group, reading from it would stop the dev server.
+        command.stdin(Stdio::null());
+
+        let mut dev_process = command.spawn().map_err(|source| WebdevError::Spawn {
+            command: self.command_line(&args),
+            source,
+        })?;
+
+        write_output(
+            dev_process.stdout.take(),
+            "dev",
+            self.output_format,
+            self.output(false),
+            on_line.clone(),
+        );
+        write_output(
+            dev_process.stderr.take(),
+            "dev",
+            self.output_format,
+            self.output(true),
+            on_line,
+        );
+
+        Ok(dev_process)
+    }
+
+    /// Run the package manager with `args` to completion, failing if it exits unsuccessfully.
+    async fn execute(
+        &self,
+        mut command: Command,
+        args: &[&str],
+        prefix: &'static str,
+    ) -> Result<(), WebdevError> {
+        let mut process = command.spawn().map_err(|source| WebdevError::Spawn {
+            command: self.command_line(args),
+            source,
+        })?;
+
+        let stdout = write_output(
+            process.stdout.take(),
+            prefix,
+            self.output_format,
+            self.output(false),
+            |_| {},
+        );
+        let stderr = write_output(
+            process.stderr.take(),
+            prefix,
+            self.output_format,
+            self.output(true),
+            |_| {},
+        );
+
+        let status = process.wait().await.map_err(|source| WebdevError::Spawn {
+            command: self.command_line(args),
+            source,
+        })?;
+
+        if !status.success() {
+            tracing::error!("{} process exited with {}", prefix, status);
+
+            return Err(WebdevError::CommandFailed {
+                command: self.command_line(args),
+                status,
+                stdout: stdout.await.unwrap_or_default(),
+                stderr: stderr.await.unwrap_or_default(),
+            });
+        }
+
         Ok(())
     }
 
-    async fn execute_dev(&self) -> Result<(), std::io::Error> {
-        let mut command = Command::new(&self.command);
-        command.current_dir(&self.root.canonicalize()?);
-        command.args(["dev"]);
+    fn command(&self, args: &[&str]) -> Result<Command, WebdevError> {
+        let mut command = Command::new(self.package_manager.command());
+        command.current_dir(self.root_dir()?);
+        command.args(args);
         command.stdout(Stdio::piped());
+        command.stderr(Stdio::piped());
 
-        let mut build_process = command.spawn()?;
+        Ok(command)
+    }
 
-        let stdout = build_process
-            .stdout
-            .take()
-            .expect("dev process did not have a handle to stdout");
+    /// Where the output of a command's stdout, or stderr if `stderr` is set, is forwarded to.
+    fn output(&self, stderr: bool) -> Box<dyn AsyncWrite + Unpin + Send> {
+        match self.output_format {
+            OutputFormat::Prefixed if stderr => Box::new(tokio::io::stderr()),
+            // Cargo only picks up directives from a build script's stdout.
+            OutputFormat::Prefixed | OutputFormat::CargoWarning => Box::new(tokio::io::stdout()),
+        }
+    }
 
-        write_stdout(stdout, "dev");
+    pub(crate) fn root_dir(&self) -> Result<PathBuf, WebdevError> {
+        self.root
+            .canonicalize()
+            .map_err(|source| WebdevError::MissingRoot {
+                path: self.root.clone(),
+                source,
+            })
+    }
 
-        tokio::spawn(async move {
-            match build_process.wait().await {
-                Ok(status) => {
-                    if !status.success() {
-                        tracing::error!("dev process exited with error");
+    fn command_line(&self, args: &[&str]) -> String {
+        std::iter::once(self.package_manager.command())
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
 
-                        std::process::exit(status.code().unwrap_or(1));
-                    }
-                }
-                Err(error) => {
-                    tracing::error!("error waiting for dev process: {}", error);
-                }
-            }
-        });
+/// Remove the socket at `path` unless a process is listening on it.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) {
+    use std::os::unix::{fs::FileTypeExt, net::UnixStream};
 
-        Ok(())
+    let is_socket = std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket());
+
+    if !is_socket || UnixStream::connect(path).is_ok() {
+        return;
+    }
+
+    tracing::debug!("removing stale dev server socket {}", path.display());
+
+    if let Err(error) = std::fs::remove_file(path) {
+        tracing::warn!("failed to remove {}: {}", path.display(), error);
     }
 }
 
+/// Directories that are never inputs of the build: dependencies, version control and the build
+/// output itself.
+pub(crate) fn is_excluded_dir(path: &Path, target: &Path) -> bool {
+    path == target
+        || path
+            .file_name()
+            .is_some_and(|name| name == "node_modules" || name == ".git")
+}
+
 #[cfg(feature = "build")]
 impl Config {
-    pub fn prebuild(&self) -> Result<(), std::io::Error> {
+    pub fn prebuild(&self) -> Result<(), WebdevError> {
         let rt = tokio::runtime::Builder::new_multi_thread()
             .enable_io()
             .build()?;
@@ -337,20 +1565,319 @@ impl Config {
     }
 }
 
-fn write_stdout(stdout: ChildStdout, prefix: &'static str) {
-    let mut reader = BufReader::new(stdout).lines();
-
+/// Forward each line of `reader` to `output` and `on_line`, returning the last
+/// [`CAPTURED_LINES`] lines once the reader is closed.
+fn write_output<R, W, F>(
+    reader: Option<R>,
+    prefix: &'static str,
+    format: OutputFormat,
+    mut output: W,
+    mut on_line: F,
+) -> JoinHandle<String>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+    F: FnMut(&str) + Send + 'static,
+{
     tokio::spawn(async move {
-        let mut output = tokio::io::stdout();
+        let Some(reader) = reader else {
+            return String::new();
+        };
+
+        let mut captured = VecDeque::with_capacity(CAPTURED_LINES);
+        let mut reader = BufReader::new(reader).lines();
 
         while let Ok(Some(line)) = reader.next_line().await {
-            output
-                .write_all(format!("webdev {prefix}: ").as_bytes())
+            let formatted = match format {
+                OutputFormat::Prefixed => format!("webdev {prefix}: {line}\n"),
+                OutputFormat::CargoWarning => format!("cargo:warning=webdev {prefix}: {line}\n"),
+            };
+
+            let written = output
+                .write_all(formatted.as_bytes())
                 .await
-                .unwrap();
-            output.write_all(line.as_bytes()).await.unwrap();
-            output.write_all(b"\n").await.unwrap();
-            output.flush().await.unwrap();
+                .and(output.flush().await);
+
+            if let Err(error) = written {
+                tracing::warn!("failed to forward {} output: {}", prefix, error);
+            }
+
+            on_line(&line);
+
+            if captured.len() == CAPTURED_LINES {
+                captured.pop_front();
+            }
+
+            captured.push_back(line);
         }
-    });
+
+        Vec::from(captured).join("\n")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Serves like Vite with `base: process.env.VITE_BASE`, which only answers below the base.
+    const DEV_SERVER: &str = r#"
+const base = process.env.VITE_BASE ?? '/';
+const port = process.env.PORT;
+
+require('http').createServer((req, res) => {
+  if (!req.url.startsWith(base)) {
+    res.statusCode = 404;
+    return res.end();
+  }
+
+  switch (req.url.slice(base.length - 1)) {
+    case '/':
+      res.setHeader('content-type', 'text/html');
+      return res.end(`<script type="module" src="${base}@vite/client"></script>`);
+    case '/@vite/client':
+      res.setHeader('content-type', 'text/javascript');
+      return res.end('// hmr');
+    default:
+      res.statusCode = 404;
+      return res.end();
+  }
+}).listen(port, '127.0.0.1', () => console.log(`  ➜  Local:   http://localhost:${port}${base}`));
+"#;
+
+    async fn get(service: &WebdevService<Body>, path: &str) -> (StatusCode, String) {
+        let res = service
+            .clone()
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    fn has_npm() -> bool {
+        let found = std::process::Command::new("npm")
+            .arg("--version")
+            .output()
+            .is_ok();
+
+        if !found {
+            eprintln!("skipping, npm is not installed");
+        }
+
+        found
+    }
+
+    #[tokio::test]
+    async fn dev_server_loads_under_base_path() {
+        if !has_npm() {
+            return;
+        }
+
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("package.json"),
+            r#"{ "name": "base-path", "private": true, "scripts": { "dev": "node server.js" } }"#,
+        )
+        .unwrap();
+        std::fs::write(root.path().join("server.js"), DEV_SERVER).unwrap();
+
+        let config = Config::new_npm(Mode::Development, root.path())
+            .ephemeral_port(PortPassing::env())
+            .base_path("/app");
+        let service = WebdevService::<Body>::new(config).await.unwrap();
+        service.ready().await.unwrap();
+
+        let (status, html) = get(&service, "/app/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains(r#"src="/app/@vite/client""#), "{html}");
+
+        let (status, _) = get(&service, "/app/@vite/client").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = get(&service, "/app").await;
+        assert_eq!(status, StatusCode::TEMPORARY_REDIRECT);
+
+        let (status, _) = get(&service, "/@vite/client").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        service.shutdown_handle().shutdown().await;
+    }
+
+    /// Listens on a random port, so it can only be reached at the URL it prints.
+    #[cfg(feature = "https")]
+    const HTTPS_DEV_SERVER: &str = r#"
+const fs = require('fs');
+
+require('https').createServer({ key: fs.readFileSync('key.pem'), cert: fs.readFileSync('cert.pem') }, (req, res) => {
+  res.end(`${req.headers['x-forwarded-proto']} ${req.url}`);
+}).listen(0, '127.0.0.1', function () {
+  console.log(`  ➜  Local:   https://localhost:${this.address().port}/`);
+});
+"#;
+
+    #[cfg(feature = "https")]
+    #[tokio::test]
+    async fn proxies_to_an_https_dev_server() {
+        if !has_npm() {
+            return;
+        }
+
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("package.json"),
+            r#"{ "name": "https", "private": true, "scripts": { "dev": "node server.js" } }"#,
+        )
+        .unwrap();
+        std::fs::write(root.path().join("server.js"), HTTPS_DEV_SERVER).unwrap();
+
+        let certified = rcgen::generate_simple_self_signed(["localhost".to_owned()]).unwrap();
+        std::fs::write(root.path().join("cert.pem"), certified.cert.pem()).unwrap();
+        std::fs::write(
+            root.path().join("key.pem"),
+            certified.signing_key.serialize_pem(),
+        )
+        .unwrap();
+
+        let config = Config::new_npm(Mode::Development, root.path())
+            .dev_server_https(HttpsOptions::default().accept_invalid_certs_for_localhost(true))
+            .readiness(Readiness::default().health_path("/"));
+        assert_eq!(config.dev_server_url(), "https://localhost:3000");
+
+        let service = WebdevService::<Body>::new(config).await.unwrap();
+        service.ready().await.unwrap();
+
+        let (status, body) = get(&service, "/src/main.ts").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "http /src/main.ts");
+
+        service.shutdown_handle().shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn install_is_recorded_without_node_modules() {
+        if !has_npm() {
+            return;
+        }
+
+        // Without dependencies npm creates no `node_modules`, like Yarn PnP and Deno.
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("package.json"),
+            r#"{ "name": "no-node-modules", "private": true }"#,
+        )
+        .unwrap();
+
+        let config = Config::new_npm(Mode::Production, root.path());
+        config.execute_install().await.unwrap();
+
+        let root = config.root_dir().unwrap();
+        assert!(!root.join("node_modules").exists());
+        assert!(config
+            .stamp_dir(&root)
+            .unwrap()
+            .join(INSTALL_STAMP)
+            .is_file());
+    }
+
+    #[tokio::test]
+    async fn build_stamp_is_kept_out_of_target() {
+        if !has_npm() {
+            return;
+        }
+
+        // Counts the builds in a file outside of `dist`, which is written from scratch.
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("package.json"),
+            r#"{ "name": "build-stamp", "private": true, "scripts": { "build": "node build.js" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            root.path().join("build.js"),
+            r#"
+const fs = require('fs');
+fs.rmSync('dist', { recursive: true, force: true });
+fs.mkdirSync('dist');
+fs.writeFileSync('dist/index.html', '<!doctype html>');
+fs.appendFileSync('builds.log', 'build\n');
+"#,
+        )
+        .unwrap();
+
+        let config = Config::new_npm(Mode::Production, root.path())
+            .build_inputs(["package.json", "build.js"])
+            .precompress(false);
+        config.execute_build().await.unwrap();
+        config.execute_build().await.unwrap();
+
+        let builds = std::fs::read_to_string(root.path().join("builds.log")).unwrap();
+        assert_eq!(builds.lines().count(), 1);
+
+        let files = std::fs::read_dir(root.path().join("dist"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, ["index.html"]);
+
+        // A deleted `target` is rebuilt although the inputs are unchanged.
+        std::fs::remove_dir_all(root.path().join("dist")).unwrap();
+        config.execute_build().await.unwrap();
+
+        let builds = std::fs::read_to_string(root.path().join("builds.log")).unwrap();
+        assert_eq!(builds.lines().count(), 2);
+        assert!(root.path().join("dist/index.html").is_file());
+
+        // Precompressing is part of the build.
+        config.precompress(true).execute_build().await.unwrap();
+
+        let builds = std::fs::read_to_string(root.path().join("builds.log")).unwrap();
+        assert_eq!(builds.lines().count(), 3);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn dev_server_socket_replaces_a_stale_one() {
+        if !has_npm() {
+            return;
+        }
+
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("package.json"),
+            r#"{ "name": "socket", "private": true, "scripts": { "dev": "node server.js" } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            root.path().join("server.js"),
+            "require('http').createServer((req, res) => res.end(`${req.headers.host} ${req.url}`)).listen('dev.sock');",
+        )
+        .unwrap();
+
+        // Left behind by a dev server that was killed.
+        let socket = root.path().join("dev.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+
+        let config =
+            Config::new_npm(Mode::Development, root.path()).dev_server_socket(socket.clone());
+        let service = WebdevService::<Body>::new(config).await.unwrap();
+        service.ready().await.unwrap();
+
+        let (status, body) = get(&service, "/src/main.ts?t=1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "localhost /src/main.ts?t=1");
+
+        service.shutdown_handle().shutdown().await;
+
+        // A socket that is listened on is kept.
+        let _ = std::fs::remove_file(&socket);
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        remove_stale_socket(&socket);
+        assert!(socket.exists());
+    }
 }